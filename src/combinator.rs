@@ -0,0 +1,1231 @@
+// combinators, which build parsers out of other parsers
+
+#[cfg(feature = "memoize")]
+use alloc::collections::BTreeMap;
+use alloc::rc::{Rc, Weak};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::OnceCell;
+#[cfg(feature = "memoize")]
+use core::cell::RefCell;
+use core::fmt::{Debug, Display};
+use core::ops::RangeInclusive;
+
+use crate::error::*;
+use crate::primitive::*;
+use crate::{Input, IntoParser, Parser, Source};
+
+// like >>=
+pub fn bind<
+    A: 'static,
+    B: 'static,
+    E: 'static,
+    F: Fn(A) -> Parser<B, E, S> + 'static,
+    S: Source + ?Sized,
+>(
+    a: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<B, E, S> {
+    let a = a.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| match (a.0)(inp) {
+        Ok((res, rest)) => (f(res).0)(rest),
+        Err(e) => Err(e),
+    }))
+}
+
+// like *>
+pub fn then<A: 'static, B: 'static, E: 'static, S: Source + ?Sized>(
+    a: impl IntoParser<E, S, Output = A>,
+    b: impl IntoParser<E, S, Output = B>,
+) -> Parser<B, E, S> {
+    let a = a.into_parser();
+    let b = b.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| match (a.0)(inp) {
+        Ok((_, rest)) => (b.0)(rest),
+        Err(e) => Err(e),
+    }))
+}
+
+// like *>, same as then
+pub fn preceded<A: 'static, B: 'static, E: 'static, S: Source + ?Sized>(
+    a: impl IntoParser<E, S, Output = A>,
+    b: impl IntoParser<E, S, Output = B>,
+) -> Parser<B, E, S> {
+    then(a, b)
+}
+
+// like <*
+pub fn terminated<A: 'static, B: 'static, E: 'static, S: Source + ?Sized>(
+    a: impl IntoParser<E, S, Output = A>,
+    b: impl IntoParser<E, S, Output = B>,
+) -> Parser<A, E, S> {
+    let a = a.into_parser();
+    let b = b.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (a.0)(inp)?;
+        let (_, rest) = (b.0)(rest)?;
+        Ok((r, rest))
+    }))
+}
+
+// like liftA2 (,)
+pub fn pair<A: 'static, B: 'static, E: 'static, S: Source + ?Sized>(
+    a: impl IntoParser<E, S, Output = A>,
+    b: impl IntoParser<E, S, Output = B>,
+) -> Parser<(A, B), E, S> {
+    let a = a.into_parser();
+    let b = b.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (ra, rest) = (a.0)(inp)?;
+        let (rb, rest) = (b.0)(rest)?;
+        Ok(((ra, rb), rest))
+    }))
+}
+
+// like between, a.k.a. delimited
+pub fn between<O: 'static, A: 'static, C: 'static, E: 'static, S: Source + ?Sized>(
+    open: impl IntoParser<E, S, Output = O>,
+    inner: impl IntoParser<E, S, Output = A>,
+    close: impl IntoParser<E, S, Output = C>,
+) -> Parser<A, E, S> {
+    let open = open.into_parser();
+    let inner = inner.into_parser();
+    let close = close.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (_, rest) = (open.0)(inp)?;
+        let (r, rest) = (inner.0)(rest)?;
+        let (_, rest) = (close.0)(rest)?;
+        Ok((r, rest))
+    }))
+}
+
+// like <$>
+pub fn map<A: 'static, B: 'static, E: 'static, F: Fn(A) -> B + 'static, S: Source + ?Sized>(
+    a: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<B, E, S> {
+    let a = a.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| match (a.0)(inp) {
+        Ok((r, remaining)) => Ok((f(r), remaining)),
+        Err(e) => Err(e),
+    }))
+}
+
+// like $>
+pub fn p_as<A: 'static, B: 'static + Clone, E: 'static, S: Source + ?Sized>(
+    a: impl IntoParser<E, S, Output = A>,
+    b: B,
+) -> Parser<B, E, S> {
+    let a = a.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| match (a.0)(inp) {
+        Ok((_, remaining)) => Ok((b.clone(), remaining)),
+        Err(e) => Err(e),
+    }))
+}
+
+// like $>, but the value is only built on success
+pub fn map_to_with<
+    A: 'static,
+    B: 'static,
+    E: 'static,
+    F: Fn() -> B + 'static,
+    S: Source + ?Sized,
+>(
+    a: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<B, E, S> {
+    let a = a.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| match (a.0)(inp) {
+        Ok((_, remaining)) => Ok((f(), remaining)),
+        Err(e) => Err(e),
+    }))
+}
+
+// changes the error of `p` when it fails, which can also change the error type. errors
+// recovered from inside `p` are only collected if they're of the type run_collecting uses
+pub fn map_err<
+    A: 'static,
+    E: 'static,
+    E2: 'static,
+    F: Fn(E) -> E2 + 'static,
+    S: Source + ?Sized,
+>(
+    p: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<A, E2, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| (p.0)(inp).map_err(&f)))
+}
+
+// like optional
+pub fn opt<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<Option<A>, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mark = inp.mark();
+        match (p.0)(inp) {
+            Ok((r, rest)) => Ok((Some(r), rest)),
+            Err(e) if e.is_cut() => Err(e),
+            Err(_) => {
+                inp.rewind(mark);
+                Ok((None, inp))
+            }
+        }
+    }))
+}
+
+// runs `p` only when flag is set, otherwise succeeds with None without consuming anything
+pub fn cond<A: 'static, E: 'static, S: Source + ?Sized>(
+    flag: bool,
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<Option<A>, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        if !flag {
+            return Ok((None, inp));
+        }
+        let (r, rest) = (p.0)(inp)?;
+        Ok((Some(r), rest))
+    }))
+}
+
+// runs p one level deeper, failing rather than going past the depth limit. the failure is
+// cut, as alternatives would only go as deep again
+fn nested<'a, A, E: ParserError, S: Source + ?Sized>(
+    p: &Parser<A, E, S>,
+    inp: Input<'a, S>,
+) -> Result<(A, Input<'a, S>), E> {
+    if inp.depth >= inp.max_depth {
+        return Err(E::from_too_deep(inp.offset).cut());
+    }
+    let deeper = Input {
+        depth: inp.depth + 1,
+        ..inp
+    };
+    let (r, rest) = (p.0)(deeper)?;
+    Ok((
+        r,
+        Input {
+            depth: inp.depth,
+            ..rest
+        },
+    ))
+}
+
+// defers building the parser until it's first run, so rules can refer to themselves
+pub fn lazy<
+    A: 'static,
+    E: ParserError + 'static,
+    F: Fn() -> Parser<A, E, S> + 'static,
+    S: Source + ?Sized,
+>(
+    f: F,
+) -> Parser<A, E, S> {
+    let cell = OnceCell::new();
+    Parser(Rc::new(move |inp: Input<S>| {
+        nested(cell.get_or_init(&f), inp)
+    }))
+}
+
+// like fix, hands f a parser that runs whatever f returns.
+// the handle only holds a weak reference so the rule doesn't keep itself alive
+pub fn recursive<
+    A: 'static,
+    E: ParserError + 'static,
+    F: FnOnce(Parser<A, E, S>) -> Parser<A, E, S>,
+    S: Source + ?Sized,
+>(
+    f: F,
+) -> Parser<A, E, S> {
+    let cell: Rc<OnceCell<Parser<A, E, S>>> = Rc::new(OnceCell::new());
+    let weak: Weak<OnceCell<Parser<A, E, S>>> = Rc::downgrade(&cell);
+    let handle = Parser(Rc::new(move |inp: Input<S>| {
+        let cell = weak
+            .upgrade()
+            .expect("recursive parser used after being dropped");
+        let p = cell
+            .get()
+            .expect("recursive parser run before being defined");
+        nested(p, inp)
+    }));
+    // the cell is fresh so this can't fail
+    let _ = cell.set(f(handle));
+    Parser(Rc::new(move |inp: Input<S>| {
+        nested(cell.get().unwrap(), inp)
+    }))
+}
+
+// packrat parsing: remembers what p gave at each offset it's been run at, so running it
+// there again, as when alternatives backtrack over the same input, is a lookup. turns
+// grammars that would backtrack exponentially into linear ones, at the cost of memory for
+// every result. the memory belongs to the parser, so clones share it, and it's forgotten
+// whenever a new run starts, which parse_iter and the readers do for each result, so it
+// only grows with what one result takes to parse. diagnostics p recorded are recorded again
+// when its result is looked up. with state nothing is remembered, as p could give something
+// else each time
+#[cfg(feature = "memoize")]
+pub fn memoize<A: Clone + 'static, E: ParserError + Clone + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    // where p started: the offset, how much input was left (which map_parser cuts short),
+    // whether more could arrive, and the depth and its limit
+    type Key = (usize, usize, bool, usize, usize);
+    // what p gave, with the offset it ended at and the diagnostics it recorded
+    type Entry<A, E> = (Result<(A, usize), E>, Vec<Diagnostic<E>>);
+    type Memo<A, E> = (usize, BTreeMap<Key, Entry<A, E>>);
+    let memo: RefCell<Memo<A, E>> = RefCell::new((usize::MAX, BTreeMap::new()));
+    Parser(Rc::new(move |inp: Input<S>| {
+        if inp.state.is_some() {
+            return (p.0)(inp);
+        }
+        let key = (
+            inp.offset,
+            inp.rest.len(),
+            inp.streaming,
+            inp.depth,
+            inp.max_depth,
+        );
+        let cached = {
+            let mut memo = memo.borrow_mut();
+            if memo.0 != inp.run {
+                *memo = (inp.run, BTreeMap::new());
+            }
+            memo.1.get(&key).cloned()
+        };
+        let result = match cached {
+            Some((result, diagnostics)) => {
+                if let Some(log) = inp.diagnostic_log::<E>() {
+                    for diagnostic in diagnostics {
+                        log.record(diagnostic)?;
+                    }
+                }
+                result
+            }
+            None => {
+                // not borrowed while p runs, as p can get back here through recursion
+                let mark = inp.mark();
+                let result = (p.0)(inp).map(|(r, rest)| (r, rest.offset));
+                let recorded = inp
+                    .diagnostic_log::<E>()
+                    .map_or_else(Vec::new, |log| log.since(mark));
+                let mut memo = memo.borrow_mut();
+                if memo.0 == inp.run {
+                    memo.1.insert(key, (result.clone(), recorded));
+                }
+                result
+            }
+        };
+        result.map(|(r, end)| (r, inp.advance(end - inp.offset)))
+    }))
+}
+
+// runs p with at most limit recursive rules (from lazy or recursive) being parsed at once,
+// instead of DEFAULT_MAX_DEPTH. worth lowering for untrusted input on a small stack
+pub fn depth_limit<A: 'static, E: 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    limit: usize,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let limited = Input {
+            max_depth: limit,
+            ..inp
+        };
+        let (r, rest) = (p.0)(limited)?;
+        Ok((
+            r,
+            Input {
+                max_depth: inp.max_depth,
+                ..rest
+            },
+        ))
+    }))
+}
+
+// commits to `p`, so that if it fails no other alternatives are tried
+// and the error from inside `p` is reported
+pub fn cut<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| (p.0)(inp).map_err(E::cut)))
+}
+
+// like try, undoes any cut inside `p` so that alternatives can still be tried if it fails
+pub fn attempt<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| (p.0)(inp).map_err(E::uncut)))
+}
+
+// reports a failure of `p` as `name` having been expected instead of whatever `p` was
+// looking for. only failures at the start are relabelled, an error from further in says
+// more about what went wrong so it's kept, as is a cut
+pub fn label<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    name: &str,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    let name = name.to_string();
+    Parser(Rc::new(move |inp: Input<S>| match (p.0)(inp) {
+        Err(e) if !e.is_cut() && e.offset() == inp.offset => {
+            Err(unexpected(Expected::Label(name.clone()), inp))
+        }
+        r => r,
+    }))
+}
+
+// notes that any failure of `p` happened while parsing what name describes. unlike label
+// nothing is renamed, so nesting these gives the whole chain of what was being parsed
+pub fn context<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    name: &str,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    let name = name.to_string();
+    Parser(Rc::new(move |inp: Input<S>| {
+        (p.0)(inp).map_err(|e| e.with_context(&name))
+    }))
+}
+
+// like label, but the relabelled error also gets the stable code code
+pub fn label_with_code<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    name: &str,
+    code: &str,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    let name = name.to_string();
+    let code = code.to_string();
+    Parser(Rc::new(move |inp: Input<S>| match (p.0)(inp) {
+        Err(e) if !e.is_cut() && e.offset() == inp.offset => {
+            Err(unexpected::<E, S>(Expected::Label(name.clone()), inp).with_code(&code))
+        }
+        r => r,
+    }))
+}
+
+// error recovery
+
+// runs `p`, and if it fails records the error and skips past the next place `sync` matches
+// so that parsing can carry on, succeeding with None. recovery only happens when the
+// errors are being collected, otherwise this fails just like `p`, as it does if sync never matches
+pub fn recover_with<A: 'static, B: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    sync: impl IntoParser<E, S, Output = B>,
+) -> Parser<Option<A>, E, S> {
+    let p = p.into_parser();
+    let sync = sync.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mark = inp.mark();
+        let e = match (p.0)(inp) {
+            Ok((r, rest)) => return Ok((Some(r), rest)),
+            Err(e) if e.is_incomplete() => return Err(e),
+            Err(e) => e,
+        };
+        let Some(log) = inp.diagnostic_log::<E>() else {
+            return Err(e);
+        };
+        // what `p` recorded before failing goes with it, only its error is reported
+        inp.rewind(mark);
+        let mut at = inp;
+        loop {
+            if let Ok((_, rest)) = (sync.0)(at) {
+                log.record(Diagnostic::error(e))?;
+                return Ok((None, rest));
+            }
+            inp.rewind(mark);
+            match at.rest.first_len() {
+                Some(n) => at = at.advance(n),
+                None => return Err(e),
+            }
+        }
+    }))
+}
+
+// runs `p`, and if it succeeds records message about what it matched with the given severity.
+// nothing is recorded unless diagnostics are being collected, and it's dropped again if
+// parsing backtracks past `p`
+pub fn emit<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    severity: Severity,
+    message: &str,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    let message = message.to_string();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (p.0)(inp)?;
+        if let Some(log) = inp.diagnostic_log::<E>() {
+            log.record(Diagnostic {
+                severity,
+                error: E::from_message(inp.offset, &message),
+            })?;
+        }
+        Ok((r, rest))
+    }))
+}
+
+// like emit, e.g. for deprecated syntax that's still accepted
+pub fn emit_warning<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    message: &str,
+) -> Parser<A, E, S> {
+    emit(p, Severity::Warning, message)
+}
+
+// user state, like getState, putState and modifyState. the parser has to be run with
+// run_with_state and a T, or these panic. changes to the state aren't undone by
+// backtracking, so it's best changed once there's no going back, e.g. after a cut
+pub fn get_state<T: Clone + 'static, E: 'static, S: Source + ?Sized>() -> Parser<T, E, S> {
+    Parser(Rc::new(|inp: Input<S>| {
+        Ok((inp.state::<T>().borrow().clone(), inp))
+    }))
+}
+
+pub fn set_state<T: Clone + 'static, E: 'static, S: Source + ?Sized>(value: T) -> Parser<(), E, S> {
+    update_state(move |state: &mut T| *state = value.clone())
+}
+
+pub fn update_state<T: 'static, E: 'static, F: Fn(&mut T) + 'static, S: Source + ?Sized>(
+    f: F,
+) -> Parser<(), E, S> {
+    Parser(Rc::new(move |inp: Input<S>| {
+        f(&mut inp.state::<T>().borrow_mut());
+        Ok(((), inp))
+    }))
+}
+
+// fails unless `p` consumes all of the input
+pub fn all_consuming<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<A, E, S> {
+    terminated(p, eof())
+}
+
+// like lookAhead
+pub fn peek<A: 'static, E: 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mark = inp.mark();
+        let (r, _) = (p.0)(inp)?;
+        // the input gets parsed again, and anything in it reported then
+        inp.rewind(mark);
+        Ok((r, inp))
+    }))
+}
+
+// the part of inp that was consumed to leave rest
+pub(crate) fn consumed_by<'a, S: Source + ?Sized>(inp: Input<'a, S>, rest: Input<S>) -> &'a S {
+    &inp.rest[..rest.offset - inp.offset]
+}
+
+// like notFollowedBy
+pub fn not<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<(), E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mark = inp.mark();
+        let r = (p.0)(inp);
+        if !r.as_ref().is_err_and(E::is_cut) {
+            inp.rewind(mark);
+        }
+        match r {
+            Ok((_, rest)) => Err(E::from_unexpected(
+                inp.offset,
+                &consumed_by(inp, rest).describe(),
+            )),
+            Err(e) if e.is_cut() => Err(e),
+            Err(_) => Ok(((), inp)),
+        }
+    }))
+}
+
+// runs `p` but returns the input it consumed instead of its result
+pub fn recognize<A: 'static, E: 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<S::Owned, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (_, rest) = (p.0)(inp)?;
+        Ok((consumed_by(inp, rest).to_owned(), rest))
+    }))
+}
+
+// runs `p` and returns its result together with the input it consumed
+pub fn consumed<A: 'static, E: 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<(A, S::Owned), E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (p.0)(inp)?;
+        Ok(((r, consumed_by(inp, rest).to_owned()), rest))
+    }))
+}
+
+// where in the input something was, as byte offsets. results can't borrow from the input,
+// since a parser has to work on input of any lifetime, so this is the way to keep hold of
+// the input matched without copying it out. of gives back the matched part of the source
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    // src has to be the input that was parsed
+    pub fn of<'a, S: Source + ?Sized>(&self, src: &'a S) -> &'a S {
+        &src[self.start..][..self.end - self.start]
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+// like recognize, but without copying what p consumed
+pub fn recognize_span<A: 'static, E: 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<Span, E, S> {
+    map(spanned(p), |(_, span)| span)
+}
+
+// runs `p` and returns its result together with where it was in the input
+pub fn spanned<A: 'static, E: 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<(A, Span), E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (p.0)(inp)?;
+        let span = Span {
+            start: inp.offset,
+            end: rest.offset,
+        };
+        Ok(((r, span), rest))
+    }))
+}
+
+// a result along with where in the input it came from, like nom's LocatedSpan
+#[derive(Debug, PartialEq, Clone)]
+pub struct Located<A> {
+    pub value: A,
+    pub span: Span,
+    pub start: Position,
+    // the name of the input, from run_named
+    pub name: Option<String>,
+}
+
+// runs `p` and returns its result together with where it was, down to the line and column
+pub fn located<A: 'static, E: 'static>(p: impl IntoParser<E, Output = A>) -> Parser<Located<A>, E> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input| {
+        let (value, rest) = (p.0)(inp)?;
+        let located = Located {
+            value,
+            span: Span {
+                start: inp.offset,
+                end: rest.offset,
+            },
+            start: inp.position(),
+            name: inp.name.map(str::to_string),
+        };
+        Ok((located, rest))
+    }))
+}
+
+// runs inner over the input matched by outer, as if that was all there was. offsets
+// inside inner still count from the start of the whole input, but lines count from the
+// start of the region
+pub fn map_parser<B: 'static, E: 'static, S: Source + ?Sized>(
+    outer: impl IntoParser<E, S, Output = S::Owned>,
+    inner: impl IntoParser<E, S, Output = B>,
+) -> Parser<B, E, S> {
+    let outer = outer.into_parser();
+    let inner = inner.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (region, rest) = (outer.0)(inp)?;
+        let region: &S = region.borrow();
+        let region = Input {
+            rest: region,
+            offset: inp.offset,
+            src: region,
+            src_offset: inp.offset,
+            // outer has already matched all of it, so the region is complete
+            streaming: false,
+            ..inp
+        };
+        let (r, _) = (inner.0)(region)?;
+        Ok((r, rest))
+    }))
+}
+
+// like map, but the mapping can fail
+pub fn map_res<
+    A: 'static,
+    B: 'static,
+    E: ParserError + 'static,
+    E2: Display,
+    F,
+    S: Source + ?Sized,
+>(
+    p: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<B, E, S>
+where
+    F: Fn(A) -> Result<B, E2> + 'static,
+{
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (p.0)(inp)?;
+        match f(r) {
+            Ok(b) => Ok((b, rest)),
+            Err(e) => Err(E::from_conversion(
+                inp.offset,
+                &consumed_by(inp, rest).describe(),
+                &e.to_string(),
+            )),
+        }
+    }))
+}
+
+// like map, but fails when f returns None
+pub fn map_opt<
+    A: 'static,
+    B: 'static,
+    E: ParserError + 'static,
+    F: Fn(A) -> Option<B> + 'static,
+    S: Source + ?Sized,
+>(
+    p: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<B, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (p.0)(inp)?;
+        match f(r) {
+            Some(b) => Ok((b, rest)),
+            None => Err(E::from_invalid(
+                inp.offset,
+                &consumed_by(inp, rest).describe(),
+            )),
+        }
+    }))
+}
+
+// fails unless the result of `p` satisfies pred
+pub fn verify<
+    A: 'static,
+    E: ParserError + 'static,
+    F: Fn(&A) -> bool + 'static,
+    S: Source + ?Sized,
+>(
+    p: impl IntoParser<E, S, Output = A>,
+    pred: F,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (r, rest) = (p.0)(inp)?;
+        if pred(&r) {
+            Ok((r, rest))
+        } else {
+            Err(E::from_invalid(
+                inp.offset,
+                &consumed_by(inp, rest).describe(),
+            ))
+        }
+    }))
+}
+
+pub fn p_or<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    left: impl IntoParser<E, S, Output = A>,
+    right: impl IntoParser<E, S, Output = A>,
+) -> Parser<A, E, S> {
+    let left = left.into_parser();
+    let right = right.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        // try left branch
+        let mark = inp.mark();
+        match (left.0)(inp) {
+            Ok(a) => Ok(a),
+            Err(e) if e.is_cut() => Err(e),
+            Err(e) => {
+                // try right branch, without anything left recorded
+                inp.rewind(mark);
+                match (right.0)(inp) {
+                    Ok(b) => Ok(b),
+                    Err(e2) if e2.is_cut() => Err(e2),
+                    Err(e2) => Err(E::merge(vec![e, e2])),
+                }
+            }
+        }
+    }))
+}
+
+// like p_or, but the parser to fall back on is built from the error `p` failed with.
+// the fallback's own error is the one reported if it fails too
+pub fn or_else<
+    A: 'static,
+    E: ParserError + 'static,
+    F: Fn(E) -> Parser<A, E, S> + 'static,
+    S: Source + ?Sized,
+>(
+    p: impl IntoParser<E, S, Output = A>,
+    f: F,
+) -> Parser<A, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mark = inp.mark();
+        match (p.0)(inp) {
+            Err(e) if !e.is_cut() => {
+                inp.rewind(mark);
+                (f(e).0)(inp)
+            }
+            r => r,
+        }
+    }))
+}
+
+// like choice, tries each parser in order. if they all fail, their errors are merged with
+// E::merge, which for ParseError keeps only the ones that got furthest into the input and
+// gathers what they expected into one Expected
+pub fn choice<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    parsers: Vec<Parser<A, E, S>>,
+) -> Parser<A, E, S> {
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mut errors = Vec::new();
+        let mark = inp.mark();
+        for p in &parsers {
+            match (p.0)(inp) {
+                Ok(r) => return Ok(r),
+                Err(e) if e.is_cut() => return Err(e),
+                Err(e) => {
+                    inp.rewind(mark);
+                    errors.push(e);
+                }
+            }
+        }
+        Err(E::merge(errors))
+    }))
+}
+
+// tuples of parsers that `permutation` can run in any order
+pub trait Permutation<A, E, S: ?Sized = str> {
+    fn run_permutation<'a>(&self, inp: Input<'a, S>) -> Result<(A, Input<'a, S>), E>;
+}
+
+macro_rules! impl_permutation {
+    ($($p:ident $r:ident $A:ident),+) => {
+        impl<$($A,)+ E: ParserError, S: Source + ?Sized> Permutation<($($A,)+), E, S>
+            for ($(Parser<$A, E, S>,)+)
+        {
+            fn run_permutation<'a>(
+                &self,
+                mut inp: Input<'a, S>,
+            ) -> Result<(($($A,)+), Input<'a, S>), E> {
+                let ($($p,)+) = self;
+                $(let mut $r = None;)+
+                loop {
+                    if $($r.is_some())&&+ {
+                        return Ok((($($r.unwrap(),)+), inp));
+                    }
+                    // each round, the first unmatched parser that succeeds is taken
+                    let mut errors = Vec::new();
+                    let mark = inp.mark();
+                    $(
+                        if $r.is_none() {
+                            match ($p.0)(inp) {
+                                Ok((r, rest)) => {
+                                    $r = Some(r);
+                                    inp = rest;
+                                    continue;
+                                }
+                                Err(e) if e.is_cut() => return Err(e),
+                                Err(e) => {
+                                    inp.rewind(mark);
+                                    errors.push(e);
+                                }
+                            }
+                        }
+                    )+
+                    return Err(E::merge(errors));
+                }
+            }
+        }
+    };
+}
+
+impl_permutation!(a ra A, b rb B);
+impl_permutation!(a ra A, b rb B, c rc C);
+impl_permutation!(a ra A, b rb B, c rc C, d rd D);
+
+// parses every component exactly once, in any order,
+// and returns the results in the order the parsers were given
+pub fn permutation<
+    A: 'static,
+    E: 'static,
+    P: Permutation<A, E, S> + 'static,
+    S: Source + ?Sized,
+>(
+    parsers: P,
+) -> Parser<A, E, S> {
+    Parser(Rc::new(move |inp: Input<S>| parsers.run_permutation(inp)))
+}
+
+// repetition
+
+// runs `p` until it fails, folding each result into `acc` and returning it with the remaining input.
+// also stops if `p` succeeds without consuming anything, otherwise it would loop forever.
+// only a cut error is passed on, any other failure just ends the loop
+fn fold_loop<'a, A, B, E: ParserError, S: Source + ?Sized>(
+    p: &Parser<A, E, S>,
+    mut inp: Input<'a, S>,
+    mut acc: B,
+    mut f: impl FnMut(B, A) -> B,
+) -> Result<(B, Input<'a, S>), E> {
+    loop {
+        let mark = inp.mark();
+        match (p.0)(inp) {
+            Ok((r, rest)) if rest.offset > inp.offset => {
+                acc = f(acc, r);
+                inp = rest;
+            }
+            Err(e) if e.is_cut() => return Err(e),
+            _ => {
+                inp.rewind(mark);
+                break;
+            }
+        }
+    }
+    Ok((acc, inp))
+}
+
+fn collect_many<'a, A, E: ParserError, S: Source + ?Sized>(
+    p: &Parser<A, E, S>,
+    inp: Input<'a, S>,
+    out: &mut Vec<A>,
+) -> Result<Input<'a, S>, E> {
+    Ok(fold_loop(p, inp, (), |_, r| out.push(r))?.1)
+}
+
+// like many
+pub fn many0<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<Vec<A>, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mut results = Vec::new();
+        let rest = collect_many(&p, inp, &mut results)?;
+        Ok((results, rest))
+    }))
+}
+
+// like some
+pub fn many1<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<Vec<A>, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (first, rest) = (p.0)(inp)?;
+        let mut results = vec![first];
+        let rest = collect_many(&p, rest, &mut results)?;
+        Ok((results, rest))
+    }))
+}
+
+// like foldl over many
+pub fn fold_many0<A: 'static, B: 'static, E: ParserError + 'static, I, F, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    init: I,
+    f: F,
+) -> Parser<B, E, S>
+where
+    I: Fn() -> B + 'static,
+    F: Fn(B, A) -> B + 'static,
+{
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| fold_loop(&p, inp, init(), &f)))
+}
+
+// like foldl over some
+pub fn fold_many1<A: 'static, B: 'static, E: ParserError + 'static, I, F, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    init: I,
+    f: F,
+) -> Parser<B, E, S>
+where
+    I: Fn() -> B + 'static,
+    F: Fn(B, A) -> B + 'static,
+{
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (first, rest) = (p.0)(inp)?;
+        fold_loop(&p, rest, f(init(), first), &f)
+    }))
+}
+
+// like manyTill, but also keeps the result of end
+pub fn many_till<A: 'static, B: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    item: impl IntoParser<E, S, Output = A>,
+    end: impl IntoParser<E, S, Output = B>,
+) -> Parser<(Vec<A>, B), E, S> {
+    let item = item.into_parser();
+    let end = end.into_parser();
+    Parser(Rc::new(move |mut inp: Input<S>| {
+        let mut results = Vec::new();
+        loop {
+            let mark = inp.mark();
+            let end_err = match (end.0)(inp) {
+                Ok((b, rest)) => return Ok(((results, b), rest)),
+                Err(e) if e.is_cut() => return Err(e),
+                Err(e) => e,
+            };
+            inp.rewind(mark);
+            match (item.0)(inp) {
+                // an item that consumes nothing would never reach end
+                Ok((_, rest)) if rest.offset == inp.offset => return Err(end_err),
+                Ok((r, rest)) => {
+                    results.push(r);
+                    inp = rest;
+                }
+                Err(e) if e.is_cut() => return Err(e),
+                Err(e) => return Err(E::merge(vec![end_err, e])),
+            }
+        }
+    }))
+}
+
+// runs `item` repeatedly with `sep` in between, after `first` has already been parsed.
+// a separator that isn't followed by another item is left unconsumed
+fn collect_sep_by<'a, A, B, E: ParserError, S: Source + ?Sized>(
+    item: &Parser<A, E, S>,
+    sep: &Parser<B, E, S>,
+    mut inp: Input<'a, S>,
+    out: &mut Vec<A>,
+) -> Result<Input<'a, S>, E> {
+    loop {
+        let mark = inp.mark();
+        let after_sep = match (sep.0)(inp) {
+            Ok((_, after_sep)) => after_sep,
+            Err(e) if e.is_cut() => return Err(e),
+            Err(_) => {
+                inp.rewind(mark);
+                break;
+            }
+        };
+        match (item.0)(after_sep) {
+            Ok((r, rest)) if rest.offset > inp.offset => {
+                out.push(r);
+                inp = rest;
+            }
+            Err(e) if e.is_cut() => return Err(e),
+            _ => {
+                inp.rewind(mark);
+                break;
+            }
+        }
+    }
+    Ok(inp)
+}
+
+// like sepBy
+pub fn sep_by<A: 'static, B: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    item: impl IntoParser<E, S, Output = A>,
+    sep: impl IntoParser<E, S, Output = B>,
+) -> Parser<Vec<A>, E, S> {
+    let item = item.into_parser();
+    let sep = sep.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let mark = inp.mark();
+        match (item.0)(inp) {
+            Ok((first, rest)) => {
+                let mut results = vec![first];
+                let rest = collect_sep_by(&item, &sep, rest, &mut results)?;
+                Ok((results, rest))
+            }
+            Err(e) if e.is_cut() => Err(e),
+            Err(_) => {
+                inp.rewind(mark);
+                Ok((Vec::new(), inp))
+            }
+        }
+    }))
+}
+
+// like sepBy1
+pub fn sep_by1<A: 'static, B: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    item: impl IntoParser<E, S, Output = A>,
+    sep: impl IntoParser<E, S, Output = B>,
+) -> Parser<Vec<A>, E, S> {
+    let item = item.into_parser();
+    let sep = sep.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (first, rest) = (item.0)(inp)?;
+        let mut results = vec![first];
+        let rest = collect_sep_by(&item, &sep, rest, &mut results)?;
+        Ok((results, rest))
+    }))
+}
+
+// like replicateM
+pub fn count<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    n: usize,
+    p: impl IntoParser<E, S, Output = A>,
+) -> Parser<Vec<A>, E, S> {
+    let p = p.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| run_count(&p, n, inp)))
+}
+
+fn run_count<'a, A, E: ParserError, S: Source + ?Sized>(
+    p: &Parser<A, E, S>,
+    n: usize,
+    mut inp: Input<'a, S>,
+) -> Result<(Vec<A>, Input<'a, S>), E> {
+    let mut results = Vec::with_capacity(n);
+    for i in 0..n {
+        match (p.0)(inp) {
+            Ok((r, rest)) => {
+                results.push(r);
+                inp = rest;
+            }
+            Err(e) => return Err(e.in_repetition(i)),
+        }
+    }
+    Ok((results, inp))
+}
+
+// parses a count, then exactly that many items
+pub fn length_count<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    count: impl IntoParser<E, S, Output = usize>,
+    item: impl IntoParser<E, S, Output = A>,
+) -> Parser<Vec<A>, E, S> {
+    let count = count.into_parser();
+    let item = item.into_parser();
+    Parser(Rc::new(move |inp: Input<S>| {
+        let (n, rest) = (count.0)(inp)?;
+        run_count(&item, n, rest)
+    }))
+}
+
+// runs `p` at least `*range.start()` and at most `*range.end()` times.
+// panics if the range is empty, as no number of matches would be within it
+pub fn repeat<A: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    range: RangeInclusive<usize>,
+) -> Parser<Vec<A>, E, S> {
+    let p = p.into_parser();
+    let (min, max) = (*range.start(), *range.end());
+    assert!(min <= max, "repeat range is empty: {:?}", range);
+    Parser(Rc::new(move |mut inp: Input<S>| {
+        let mut results = Vec::new();
+        for i in 0..max {
+            let mark = inp.mark();
+            match (p.0)(inp) {
+                Ok((_, rest)) if i >= min && rest.offset == inp.offset => {
+                    inp.rewind(mark);
+                    break;
+                }
+                Ok((r, rest)) => {
+                    results.push(r);
+                    inp = rest;
+                }
+                Err(e) if i < min || e.is_cut() => return Err(e.in_repetition(i)),
+                Err(_) => {
+                    inp.rewind(mark);
+                    break;
+                }
+            }
+        }
+        Ok((results, inp))
+    }))
+}
+
+// the most used combinators as methods, so that chains of them read in the order they run
+impl<A: 'static, E: 'static, S: Source + ?Sized> Parser<A, E, S> {
+    pub fn map<B: 'static, F: Fn(A) -> B + 'static>(self, f: F) -> Parser<B, E, S> {
+        map(self, f)
+    }
+
+    pub fn map_err<E2: 'static, F: Fn(E) -> E2 + 'static>(self, f: F) -> Parser<A, E2, S> {
+        map_err(self, f)
+    }
+
+    pub fn bind<B: 'static, F: Fn(A) -> Parser<B, E, S> + 'static>(self, f: F) -> Parser<B, E, S> {
+        bind(self, f)
+    }
+
+    // like then, keeping the result of next instead
+    pub fn then<P: IntoParser<E, S>>(self, next: P) -> Parser<P::Output, E, S> {
+        then(self, next.into_parser())
+    }
+
+    // like terminated
+    pub fn skip<P: IntoParser<E, S>>(self, next: P) -> Parser<A, E, S> {
+        terminated(self, next.into_parser())
+    }
+
+    pub fn pair<P: IntoParser<E, S>>(self, next: P) -> Parser<(A, P::Output), E, S> {
+        pair(self, next.into_parser())
+    }
+
+    pub fn recognize(self) -> Parser<S::Owned, E, S> {
+        recognize(self)
+    }
+
+    pub fn spanned(self) -> Parser<(A, Span), E, S> {
+        spanned(self)
+    }
+}
+
+impl<A: 'static, E: ParserError + 'static, S: Source + ?Sized> Parser<A, E, S> {
+    pub fn or<P: IntoParser<E, S, Output = A>>(self, other: P) -> Parser<A, E, S> {
+        p_or(self, other.into_parser())
+    }
+
+    pub fn opt(self) -> Parser<Option<A>, E, S> {
+        opt(self)
+    }
+
+    // like many0
+    pub fn many(self) -> Parser<Vec<A>, E, S> {
+        many0(self)
+    }
+
+    pub fn many1(self) -> Parser<Vec<A>, E, S> {
+        many1(self)
+    }
+
+    pub fn sep_by<P: IntoParser<E, S>>(self, sep: P) -> Parser<Vec<A>, E, S> {
+        sep_by(self, sep.into_parser())
+    }
+
+    pub fn verify<F: Fn(&A) -> bool + 'static>(self, pred: F) -> Parser<A, E, S> {
+        verify(self, pred)
+    }
+
+    pub fn label(self, name: &str) -> Parser<A, E, S> {
+        label(self, name)
+    }
+
+    pub fn context(self, name: &str) -> Parser<A, E, S> {
+        context(self, name)
+    }
+
+    pub fn cut(self) -> Parser<A, E, S> {
+        cut(self)
+    }
+
+    pub fn attempt(self) -> Parser<A, E, S> {
+        attempt(self)
+    }
+}
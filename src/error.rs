@@ -0,0 +1,619 @@
+// what goes wrong while parsing, and how it's told to whoever's parsing
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::RefCell;
+use core::error::Error;
+use core::fmt::{self, Debug, Display};
+use core::ops::Range;
+#[cfg(feature = "std")]
+use std::io;
+
+// what a parser was looking for when it failed
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expected {
+    // exactly this text
+    Literal(String),
+    // any char of this kind, like a digit
+    Class(String),
+    // something with this name, given by a label or by the parser
+    Label(String),
+    EndOfInput,
+}
+
+impl Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expected::Literal(s) | Expected::Class(s) | Expected::Label(s) => write!(f, "{}", s),
+            Expected::EndOfInput => write!(f, "end of input"),
+        }
+    }
+}
+
+// how much more input a streaming parser needs to carry on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Needed {
+    Unknown,
+    // at least this many more bytes, or items
+    Size(usize),
+}
+
+// the usize in each leaf variant is the offset into the input where the error occurred,
+// counted in bytes for text
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseError {
+    EOF(usize, Expected),
+    // what was expected and the input found in its place, with the span of the found
+    // input's first line
+    Mismatch(Range<usize>, Expected, String),
+    // everything that could have come next at one place, and what was found there instead,
+    // None being the end of the input
+    Expected(usize, Vec<Expected>, Option<String>),
+    // failures from alternatives that can't be merged into one Expected
+    Multiple(Vec<ParseError>),
+    // the (zero-based) iteration of a repeated parser that failed, and why
+    Repetition(usize, Box<ParseError>),
+    // input that matched a parser which was required not to match
+    Unexpected(usize, String),
+    // input that parsed successfully but whose value was rejected
+    Invalid(usize, String),
+    // input that parsed successfully but failed to convert, with the conversion error's message
+    Conversion(usize, String, String),
+    // a failure after a `cut`, alternatives shouldn't be tried once this is seen
+    Cut(Box<ParseError>),
+    // a failure while parsing what the string describes
+    Context(String, Box<ParseError>),
+    // something said about the input that isn't about a failure, like a warning
+    Message(usize, String),
+    // an error with a code given to it by the user, instead of the one for its kind
+    Coded(String, Box<ParseError>),
+    // the input ran out while streaming, it's not wrong but more of it has to arrive
+    Incomplete(usize, Needed),
+    // the input nested deeper than the depth limit allows
+    TooDeep(usize),
+    // parsing was asked to start past the end of the input or inside a char
+    BadOffset(usize),
+}
+
+impl ParseError {
+    // a Mismatch of expected for found at offset. only found's first line is kept, as
+    // that's what the span covers
+    pub fn mismatch(offset: usize, expected: Expected, found: &str) -> ParseError {
+        let found = first_line(found);
+        ParseError::Mismatch(offset..offset + found.len(), expected, found.to_string())
+    }
+
+    // byte offset into the input where the error occurred.
+    // for Multiple it's the furthest any of the alternatives got
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseError::Mismatch(span, _, _) => span.start,
+            ParseError::EOF(at, _)
+            | ParseError::Expected(at, _, _)
+            | ParseError::Unexpected(at, _)
+            | ParseError::Invalid(at, _)
+            | ParseError::Conversion(at, _, _)
+            | ParseError::Message(at, _)
+            | ParseError::Incomplete(at, _)
+            | ParseError::TooDeep(at)
+            | ParseError::BadOffset(at) => *at,
+            ParseError::Multiple(es) => es.iter().map(ParseError::offset).max().unwrap_or(0),
+            ParseError::Repetition(_, e)
+            | ParseError::Cut(e)
+            | ParseError::Context(_, e)
+            | ParseError::Coded(_, e) => e.offset(),
+        }
+    }
+
+    // a stable code for the kind of error, for tools to filter on. these never change
+    // meaning, new kinds get new codes. Multiple and Message have none unless given one
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            ParseError::Mismatch(..) => Some("E0001"),
+            ParseError::EOF(..) => Some("E0002"),
+            ParseError::Expected(..) => Some("E0003"),
+            ParseError::Unexpected(..) => Some("E0004"),
+            ParseError::Invalid(..) => Some("E0005"),
+            ParseError::Conversion(..) => Some("E0006"),
+            ParseError::Incomplete(..) => Some("E0007"),
+            ParseError::TooDeep(_) => Some("E0008"),
+            ParseError::BadOffset(_) => Some("E0009"),
+            ParseError::Multiple(_) | ParseError::Message(..) => None,
+            ParseError::Repetition(_, e) | ParseError::Cut(e) | ParseError::Context(_, e) => {
+                e.code()
+            }
+            ParseError::Coded(code, _) => Some(code),
+        }
+    }
+
+    // where the error occurred in src, which has to be the input that was parsed
+    pub fn position(&self, src: &str) -> Position {
+        Position::at(src, self.offset())
+    }
+
+    // what went wrong, without saying where
+    pub fn message(&self) -> String {
+        match self {
+            ParseError::EOF(_, expected) => format!("expected {}, found end of input", expected),
+            ParseError::Mismatch(_, expected, found) => {
+                format!("expected {}, found {:?}", expected, first_line(found))
+            }
+            ParseError::Expected(_, expected, found) => {
+                let found = match found {
+                    Some(found) => format!("{:?}", first_line(found)),
+                    None => "end of input".to_string(),
+                };
+                let expected: Vec<String> = expected.iter().map(Expected::to_string).collect();
+                format!("expected one of {}, found {}", expected.join(", "), found)
+            }
+            ParseError::Multiple(es) => es
+                .iter()
+                .map(ParseError::message)
+                .collect::<Vec<_>>()
+                .join("; "),
+            ParseError::Repetition(_, e) | ParseError::Cut(e) | ParseError::Coded(_, e) => {
+                e.message()
+            }
+            ParseError::Context(context, e) => {
+                format!("{}, while parsing {}", e.message(), context)
+            }
+            ParseError::Unexpected(_, found) => format!("unexpected {:?}", first_line(found)),
+            ParseError::Invalid(_, found) => format!("invalid {:?}", first_line(found)),
+            ParseError::Conversion(_, found, reason) => {
+                format!("couldn't convert {:?}: {}", first_line(found), reason)
+            }
+            ParseError::Message(_, message) => message.clone(),
+            ParseError::Incomplete(_, Needed::Unknown) => "more input needed".to_string(),
+            ParseError::Incomplete(_, Needed::Size(n)) => format!("at least {} more needed", n),
+            ParseError::TooDeep(_) => "input nested too deeply".to_string(),
+            ParseError::BadOffset(_) => {
+                "can't start parsing outside the input or inside a char".to_string()
+            }
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Multiple(es) => {
+                for (i, e) in es.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                Ok(())
+            }
+            ParseError::Repetition(_, e) | ParseError::Cut(e) | ParseError::Coded(_, e) => {
+                write!(f, "{}", e)
+            }
+            ParseError::Context(context, e) => write!(f, "{}, while parsing {}", e, context),
+            e => write!(f, "{} at offset {}", e.message(), e.offset()),
+        }
+    }
+}
+
+impl Error for ParseError {}
+
+// found text can run on to the end of the input, only the part on the error's line is shown.
+// the line break itself is kept if that's all there is
+pub(crate) fn first_line(s: &str) -> &str {
+    let end = s.find('\n').unwrap_or(s.len());
+    match s[..end].trim_end_matches('\r') {
+        "" => &s[..s.len().min(end + 1)],
+        line => line,
+    }
+}
+
+// a place in the input, with lines and columns counted from 1.
+// columns count chars, so a tab or a wide char is still one column
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    // works out the line and column of a byte offset into src
+    pub fn at(src: &str, offset: usize) -> Position {
+        let before = &src[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Position {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+// what parsers need from an error type. the combinators and primitives work with any error
+// implementing this, with ParseError being the one that comes with the crate. the kinds of
+// failure without a method of their own default to a mismatch, and errors that don't track
+// cuts can always be backtracked past, unless they're incomplete. there's no default for
+// being incomplete, as streaming and the readers only work if it's told apart from the
+// input being wrong
+pub trait ParserError: Sized {
+    // expected was wanted but found was there instead, spanning span
+    fn from_mismatch(span: Range<usize>, expected: Expected, found: &str) -> Self;
+    // expected was wanted at offset but the input had ended
+    fn from_eof(offset: usize, expected: Expected) -> Self;
+    // combines the errors from alternatives that all failed
+    fn merge(errors: Vec<Self>) -> Self;
+    // notes that self happened while parsing what context describes
+    fn with_context(self, context: &str) -> Self;
+    // byte offset into the input where the error occurred
+    fn offset(&self) -> usize;
+    // input ran out at offset while streaming, and at least needed more is wanted
+    fn from_incomplete(offset: usize, needed: Needed) -> Self;
+    // whether self only means more input is needed, not that the input is wrong
+    fn is_incomplete(&self) -> bool;
+
+    // found matched a parser which was required not to match
+    fn from_unexpected(offset: usize, found: &str) -> Self {
+        let span = offset..offset + found.len();
+        Self::from_mismatch(span, Expected::Label("something else".to_string()), found)
+    }
+
+    // found parsed successfully but its value was rejected
+    fn from_invalid(offset: usize, found: &str) -> Self {
+        let span = offset..offset + found.len();
+        Self::from_mismatch(span, Expected::Label("something valid".to_string()), found)
+    }
+
+    // found parsed successfully but failed to convert, for the given reason
+    fn from_conversion(offset: usize, found: &str, _reason: &str) -> Self {
+        Self::from_invalid(offset, found)
+    }
+
+    // a message about the input at offset, for diagnostics that aren't failures
+    fn from_message(offset: usize, message: &str) -> Self {
+        Self::from_invalid(offset, message)
+    }
+
+    // rules had been entered more times than the depth limit allows by offset
+    fn from_too_deep(offset: usize) -> Self {
+        Self::from_message(offset, "input nested too deeply")
+    }
+
+    // parsing was asked to start at offset, which is past the end of the input or inside a char
+    fn from_bad_offset(offset: usize) -> Self {
+        Self::from_message(
+            offset,
+            "can't start parsing outside the input or inside a char",
+        )
+    }
+
+    // self happened on the (zero-based) iteration i of a repeated parser
+    fn in_repetition(self, _i: usize) -> Self {
+        self
+    }
+
+    // gives self the stable code code, for errors that have them
+    fn with_code(self, _code: &str) -> Self {
+        self
+    }
+
+    // whether alternatives shouldn't be tried after self. they can't be until the input
+    // that's missing has arrived either
+    fn is_cut(&self) -> bool {
+        self.is_incomplete()
+    }
+
+    // marks self so that no alternatives are tried after it
+    fn cut(self) -> Self {
+        self
+    }
+
+    // undoes cut
+    fn uncut(self) -> Self {
+        self
+    }
+}
+
+impl ParserError for ParseError {
+    fn from_mismatch(span: Range<usize>, expected: Expected, found: &str) -> Self {
+        ParseError::Mismatch(span, expected, found.to_string())
+    }
+
+    fn from_eof(offset: usize, expected: Expected) -> Self {
+        ParseError::EOF(offset, expected)
+    }
+
+    // only the errors from the alternatives that got furthest into the input are kept, as they're
+    // the likeliest to be what was meant. of those, nested Multiples are flattened, duplicates
+    // dropped, and whatever was expected is gathered into a single Expected
+    fn merge(errors: Vec<Self>) -> Self {
+        let furthest = errors.iter().map(ParseError::offset).max().unwrap_or(0);
+        let mut merged = Vec::new();
+        for e in errors {
+            merge_into(&mut merged, e, furthest);
+        }
+        // an Expected with only one thing in it reads better as what it came from
+        let mut merged: Vec<ParseError> = merged
+            .into_iter()
+            .map(|e| match e {
+                ParseError::Expected(at, mut expected, found) if expected.len() == 1 => {
+                    let expected = expected.pop().unwrap();
+                    match found {
+                        Some(found) => ParseError::mismatch(at, expected, &found),
+                        None => ParseError::EOF(at, expected),
+                    }
+                }
+                e => e,
+            })
+            .collect();
+        if merged.len() == 1 {
+            merged.pop().unwrap()
+        } else {
+            ParseError::Multiple(merged)
+        }
+    }
+
+    // a cut is kept outermost so that it still stops alternation. so is being incomplete,
+    // there's nothing to say about where in the input it is yet
+    fn with_context(self, context: &str) -> Self {
+        match self {
+            ParseError::Cut(e) => ParseError::Cut(Box::new(e.with_context(context))),
+            e @ ParseError::Incomplete(..) => e,
+            e => ParseError::Context(context.to_string(), Box::new(e)),
+        }
+    }
+
+    fn offset(&self) -> usize {
+        ParseError::offset(self)
+    }
+
+    fn from_incomplete(offset: usize, needed: Needed) -> Self {
+        ParseError::Incomplete(offset, needed)
+    }
+
+    fn is_incomplete(&self) -> bool {
+        matches!(self, ParseError::Incomplete(..))
+    }
+
+    fn from_unexpected(offset: usize, found: &str) -> Self {
+        ParseError::Unexpected(offset, found.to_string())
+    }
+
+    fn from_invalid(offset: usize, found: &str) -> Self {
+        ParseError::Invalid(offset, found.to_string())
+    }
+
+    fn from_conversion(offset: usize, found: &str, reason: &str) -> Self {
+        ParseError::Conversion(offset, found.to_string(), reason.to_string())
+    }
+
+    fn from_message(offset: usize, message: &str) -> Self {
+        ParseError::Message(offset, message.to_string())
+    }
+
+    fn from_too_deep(offset: usize) -> Self {
+        ParseError::TooDeep(offset)
+    }
+
+    fn from_bad_offset(offset: usize) -> Self {
+        ParseError::BadOffset(offset)
+    }
+
+    // like with_context, a cut stays outermost
+    fn in_repetition(self, i: usize) -> Self {
+        match self {
+            ParseError::Cut(e) => ParseError::Cut(Box::new(ParseError::Repetition(i, e))),
+            e @ ParseError::Incomplete(..) => e,
+            e => ParseError::Repetition(i, Box::new(e)),
+        }
+    }
+
+    // a cut stays outermost here too, and a new code replaces an old one
+    fn with_code(self, code: &str) -> Self {
+        match self {
+            ParseError::Cut(e) => ParseError::Cut(Box::new(e.with_code(code))),
+            e @ ParseError::Incomplete(..) => e,
+            ParseError::Coded(_, e) => ParseError::Coded(code.to_string(), e),
+            e => ParseError::Coded(code.to_string(), Box::new(e)),
+        }
+    }
+
+    // alternatives can't be tried until the input that's missing has arrived either
+    fn is_cut(&self) -> bool {
+        matches!(self, ParseError::Cut(_) | ParseError::Incomplete(..))
+    }
+
+    fn cut(self) -> Self {
+        match self {
+            ParseError::Cut(e) => ParseError::Cut(e),
+            e @ ParseError::Incomplete(..) => e,
+            e => ParseError::Cut(Box::new(e)),
+        }
+    }
+
+    fn uncut(self) -> Self {
+        match self {
+            ParseError::Cut(e) => *e,
+            e => e,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+// an error, or something less serious, found while parsing with run_collecting
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic<E> {
+    pub severity: Severity,
+    pub error: E,
+}
+
+impl<E> Diagnostic<E> {
+    pub fn error(error: E) -> Diagnostic<E> {
+        Diagnostic {
+            severity: Severity::Error,
+            error,
+        }
+    }
+
+    pub fn warning(error: E) -> Diagnostic<E> {
+        Diagnostic {
+            severity: Severity::Warning,
+            error,
+        }
+    }
+}
+
+// where diagnostics are recorded while collecting them, up to limit of them so that
+// pathological input can't produce endless errors
+pub(crate) struct DiagnosticLog<E> {
+    diagnostics: RefCell<Vec<Diagnostic<E>>>,
+    limit: usize,
+}
+
+impl<E: ParserError> DiagnosticLog<E> {
+    pub fn new(limit: usize) -> DiagnosticLog<E> {
+        DiagnosticLog {
+            diagnostics: RefCell::new(Vec::new()),
+            limit,
+        }
+    }
+
+    // records diagnostic, or if the limit has been reached returns the error
+    // that parsing stops with instead. it's cut so that nothing carries on past it
+    pub fn record(&self, diagnostic: Diagnostic<E>) -> Result<(), E> {
+        let mut diagnostics = self.diagnostics.borrow_mut();
+        if diagnostics.len() >= self.limit {
+            let at = diagnostic.error.offset();
+            return Err(E::from_message(at, "too many errors, stopping").cut());
+        }
+        diagnostics.push(diagnostic);
+        Ok(())
+    }
+
+    // what's been recorded since mark, see Input::mark
+    #[cfg(feature = "memoize")]
+    pub fn since(&self, mark: usize) -> Vec<Diagnostic<E>>
+    where
+        E: Clone,
+    {
+        self.diagnostics.borrow()[mark..].to_vec()
+    }
+
+    pub fn into_inner(self) -> Vec<Diagnostic<E>> {
+        self.diagnostics.into_inner()
+    }
+}
+
+// what Input keeps of a DiagnosticLog, so that backtracking can undo what was recorded
+// without knowing what E is
+pub(crate) trait Log {
+    fn as_any(&self) -> &dyn Any;
+    fn len(&self) -> usize;
+    fn truncate(&self, len: usize);
+}
+
+// like the Debug of dyn Any, as there's no knowing what's in it
+impl Debug for dyn Log + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Log").finish_non_exhaustive()
+    }
+}
+
+impl<E: 'static> Log for DiagnosticLog<E> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn len(&self) -> usize {
+        self.diagnostics.borrow().len()
+    }
+
+    fn truncate(&self, len: usize) {
+        self.diagnostics.borrow_mut().truncate(len);
+    }
+}
+
+// adds e to merged if it happened at furthest, see ParserError::merge for ParseError.
+// an error is only turned into an Expected once there's something to gather it with,
+// so one that's alone keeps its span
+pub(crate) fn merge_into(merged: &mut Vec<ParseError>, e: ParseError, furthest: usize) {
+    if e.offset() != furthest || merged.contains(&e) {
+        return;
+    }
+    if let ParseError::Multiple(es) = e {
+        for e in es {
+            merge_into(merged, e, furthest);
+        }
+        return;
+    }
+    let Some((at, expected, _)) = expectations(&e) else {
+        merged.push(e);
+        return;
+    };
+    for m in merged.iter_mut() {
+        match expectations(m) {
+            Some((other_at, mut items, found)) if other_at == at => {
+                for item in expected {
+                    if !items.contains(&item) {
+                        items.push(item);
+                    }
+                }
+                *m = ParseError::Expected(at, items, found);
+                return;
+            }
+            _ => {}
+        }
+    }
+    merged.push(e);
+}
+
+// where e wanted what it did and what it found there instead, if it's a failure to match
+pub(crate) fn expectations(e: &ParseError) -> Option<(usize, Vec<Expected>, Option<String>)> {
+    match e {
+        ParseError::Mismatch(span, expected, found) => {
+            Some((span.start, vec![expected.clone()], Some(found.clone())))
+        }
+        ParseError::EOF(at, expected) => Some((*at, vec![expected.clone()], None)),
+        ParseError::Expected(at, expected, found) => Some((*at, expected.clone(), found.clone())),
+        _ => None,
+    }
+}
+
+// why a ReadIter failed
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum ReadError<E> {
+    Io(io::Error),
+    Parse(E),
+}
+
+#[cfg(feature = "std")]
+impl<E: Display> Display for ReadError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "{}", e),
+            ReadError::Parse(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Debug + Display> Error for ReadError<E> {}
@@ -1,135 +1,1001 @@
-#![feature(type_alias_impl_trait)]
-#![feature(fn_traits)]
+// without the std feature the crate only needs alloc, so it works in no_std too
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-use std::ops::{BitOr, BitXor, BitXorAssign};
-use std::rc::Rc;
+extern crate alloc;
 
-#[derive(Debug, PartialEq)]
-enum ParseError {
-    EOF(String),
-    Mismatch(String, String),
-    Multiple(Vec<ParseError>),
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use core::ops::{Add, BitOr, BitXor, Index, Not, RangeFrom, RangeTo, Shl, Shr};
+#[cfg(feature = "mmap")]
+use std::fs::File;
+#[cfg(feature = "async")]
+use std::future::poll_fn;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+#[cfg(feature = "mmap")]
+use std::path::Path;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+#[cfg(feature = "async")]
+use std::task::{Context, Poll};
+
+#[cfg(feature = "async")]
+use futures_core::Stream;
+#[cfg(feature = "async")]
+use futures_io::AsyncRead;
+#[cfg(feature = "memoize")]
+use portable_atomic::{AtomicUsize, Ordering};
+
+pub mod combinator;
+pub mod error;
+pub mod parse;
+pub mod prelude;
+pub mod primitive;
+pub mod report;
+
+use combinator::*;
+use error::*;
+
+// what can be parsed: text, or a slice of bytes or of anything else. offsets and lengths
+// count in the slice's own units, which for text are bytes
+pub trait Source:
+    Index<RangeFrom<usize>, Output = Self>
+    + Index<RangeTo<usize>, Output = Self>
+    + ToOwned<Owned: 'static>
+    + Debug
+    + 'static
+{
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // the length of the first item, a char for text, or None if there's nothing left
+    fn first_len(&self) -> Option<usize>;
+    // whether offset is at the start of an item or the end of them all, where parsing
+    // can start. for text it has to be in it and not in the middle of a char
+    fn is_boundary(&self, offset: usize) -> bool;
+    // how much of this an error finding it should point at, for text its first line
+    fn found_len(&self) -> usize;
+    // how this is shown in an error that found it
+    fn describe(&self) -> String;
+}
+
+impl Source for str {
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+
+    fn first_len(&self) -> Option<usize> {
+        self.chars().next().map(char::len_utf8)
+    }
+
+    fn is_boundary(&self, offset: usize) -> bool {
+        self.is_char_boundary(offset)
+    }
+
+    fn found_len(&self) -> usize {
+        first_line(self).len()
+    }
+
+    fn describe(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl<T: Clone + Debug + 'static> Source for [T] {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn first_len(&self) -> Option<usize> {
+        (!self.is_empty()).then_some(1)
+    }
+
+    fn is_boundary(&self, offset: usize) -> bool {
+        offset <= self.len()
+    }
+
+    fn found_len(&self) -> usize {
+        self.len().min(1)
+    }
+
+    // a single item is shown on its own, as what was expected usually is
+    fn describe(&self) -> String {
+        match self {
+            [item] => format!("{:?}", item),
+            items => format!("{:?}", items),
+        }
+    }
+}
+
+// the input left to parse, along with how far into the whole input it starts
+#[derive(Debug)]
+pub struct Input<'a, S: ?Sized = str> {
+    pub rest: &'a S,
+    pub offset: usize,
+    // the input rest is the end of, from as near the start as is still around. that's all
+    // of it except in parse_reader, where what's been parsed is dropped, and in map_parser,
+    // where it's the region being parsed
+    pub(crate) src: &'a S,
+    // the offset src starts at
+    pub(crate) src_offset: usize,
+    // what the input is called, like a file name, if it's been given one
+    pub(crate) name: Option<&'a str>,
+    // where diagnostics get recorded, a DiagnosticLog<E> for the E being parsed with.
+    // None if nobody is collecting them
+    pub(crate) diagnostics: Option<&'a dyn Log>,
+    // the user state, a RefCell<T> for the T passed to run_with_state
+    pub(crate) state: Option<&'a dyn Any>,
+    // whether rest might only be the start of what's left, with more still to arrive
+    pub(crate) streaming: bool,
+    // how many recursive rules are being parsed at once, and how many can be before parsing
+    // fails instead of overflowing the stack
+    pub(crate) depth: usize,
+    pub(crate) max_depth: usize,
+    // which run this is, so what memoize remembers from one isn't used in another
+    #[cfg(feature = "memoize")]
+    pub(crate) run: usize,
+}
+
+// the run the next Input::new starts. portable_atomic's, as core's has no fetch_add on
+// targets without compare-and-swap (where portable_atomic needs its critical-section feature)
+#[cfg(feature = "memoize")]
+static RUNS: AtomicUsize = AtomicUsize::new(0);
+
+// the depth limit unless depth_limit says otherwise. deep enough for anything written by
+// hand, and shallow enough for the stack of a thread that hasn't been given a bigger one
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+// not derived, as that would need S to be Copy
+impl<S: ?Sized> Clone for Input<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: ?Sized> Copy for Input<'_, S> {}
+
+impl<'a, S: Source + ?Sized> Input<'a, S> {
+    pub fn new(src: &'a S) -> Input<'a, S> {
+        Input {
+            rest: src,
+            offset: 0,
+            src,
+            src_offset: 0,
+            name: None,
+            diagnostics: None,
+            state: None,
+            streaming: false,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            #[cfg(feature = "memoize")]
+            run: RUNS.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    // the same input, but starting a new run, so memoize forgets what it's remembered
+    pub(crate) fn new_run(self) -> Input<'a, S> {
+        Input {
+            #[cfg(feature = "memoize")]
+            run: RUNS.fetch_add(1, Ordering::Relaxed),
+            ..self
+        }
+    }
+
+    // like new, but diagnostics are recorded in log
+    pub(crate) fn collecting<E: 'static>(src: &'a S, log: &'a DiagnosticLog<E>) -> Input<'a, S> {
+        Input {
+            diagnostics: Some(log),
+            ..Input::new(src)
+        }
+    }
+
+    // the log of diagnostics, if there is one for E
+    pub(crate) fn diagnostic_log<E: 'static>(&self) -> Option<&'a DiagnosticLog<E>> {
+        self.diagnostics.and_then(|log| log.as_any().downcast_ref())
+    }
+
+    // how many diagnostics have been recorded so far. a parser that backtracks to here
+    // hands this to rewind, so that nothing recorded on the way gets reported
+    pub(crate) fn mark(&self) -> usize {
+        self.diagnostics.map_or(0, |log| log.len())
+    }
+
+    // drops whatever was recorded since mark
+    pub(crate) fn rewind(&self, mark: usize) {
+        if let Some(log) = self.diagnostics {
+            log.truncate(mark);
+        }
+    }
+
+    // the user state, which has to be a T
+    pub(crate) fn state<T: 'static>(&self) -> &'a RefCell<T> {
+        self.state
+            .and_then(|state| state.downcast_ref())
+            .expect("parser uses state of a type it wasn't run with")
+    }
+
+    // the input after the next n bytes, or items
+    pub fn advance(self, n: usize) -> Input<'a, S> {
+        Input {
+            rest: &self.rest[n..],
+            offset: self.offset + n,
+            ..self
+        }
+    }
+
+    // offset of the end of the whole input
+    pub fn end_offset(self) -> usize {
+        self.offset + self.rest.len()
+    }
+
+    // the error for running out of input while expecting expected. when streaming
+    // that only means at least needed more input has to arrive
+    pub fn ended<E: ParserError>(self, expected: Expected, needed: Needed) -> E {
+        if self.streaming {
+            E::from_incomplete(self.end_offset(), needed)
+        } else {
+            E::from_eof(self.end_offset(), expected)
+        }
+    }
+
+    pub fn incomplete<E: ParserError>(self) -> E {
+        E::from_incomplete(self.end_offset(), Needed::Unknown)
+    }
+
+    // the input starting at suffix, which has to be a suffix of rest
+    pub fn skip_to(self, suffix: &'a S) -> Input<'a, S> {
+        self.advance(self.rest.len() - suffix.len())
+    }
+}
+
+impl Input<'_> {
+    // where rest starts. lines are counted from the start of src
+    pub fn position(self) -> Position {
+        Position {
+            offset: self.offset,
+            ..Position::at(self.src, self.offset - self.src_offset)
+        }
+    }
+}
+
+// what a parser runs, parsing from the start of the input to give a result and the
+// input after it
+pub type ParseFn<A, E, S = str> = dyn Fn(Input<S>) -> Result<(A, Input<S>), E>;
+
+// parses an S, which by default is text. cloning a parser shares it rather than copying
+// it, so one parser can be used in as many places as it's needed
+pub struct Parser<A, E, S: ?Sized = str>(pub Rc<ParseFn<A, E, S>>);
+
+// not derived, as that would need A and E to be Clone
+impl<A, E, S: ?Sized> Clone for Parser<A, E, S> {
+    fn clone(&self) -> Self {
+        Parser(Rc::clone(&self.0))
+    }
+}
+
+// what can stand in for a parser: a char for p_char, a &str for p_str, or a function that
+// parses by hand. the chainable methods and the operators take these, so literals don't need
+// wrapping, like `'('.then(expr).skip(')')` or `between('(', expr, ')')`. a closure has to be
+// passed to Parser first, as only there can rust tell that what it returns borrows its input
+pub trait IntoParser<E = ParseError, S: ?Sized = str> {
+    type Output: 'static;
+
+    fn into_parser(self) -> Parser<Self::Output, E, S>;
+
+    fn then<P: IntoParser<E, S>>(self, next: P) -> Parser<P::Output, E, S>
+    where
+        Self: Sized,
+        E: 'static,
+        S: Source,
+    {
+        self.into_parser().then(next)
+    }
+
+    fn skip<P: IntoParser<E, S>>(self, next: P) -> Parser<Self::Output, E, S>
+    where
+        Self: Sized,
+        E: 'static,
+        S: Source,
+    {
+        self.into_parser().skip(next)
+    }
+
+    fn pair<P: IntoParser<E, S>>(self, next: P) -> Parser<(Self::Output, P::Output), E, S>
+    where
+        Self: Sized,
+        E: 'static,
+        S: Source,
+    {
+        self.into_parser().pair(next)
+    }
+
+    fn or<P: IntoParser<E, S, Output = Self::Output>>(self, other: P) -> Parser<Self::Output, E, S>
+    where
+        Self: Sized,
+        E: ParserError + 'static,
+        S: Source,
+    {
+        self.into_parser().or(other)
+    }
+}
+
+impl<A: 'static, E, S: ?Sized> IntoParser<E, S> for Parser<A, E, S> {
+    type Output = A;
+
+    fn into_parser(self) -> Parser<A, E, S> {
+        self
+    }
+}
+
+impl<E: ParserError + 'static> IntoParser<E> for char {
+    type Output = char;
+
+    fn into_parser(self) -> Parser<char, E> {
+        primitive::p_char(self)
+    }
+}
+
+impl<E: ParserError + 'static> IntoParser<E> for &str {
+    type Output = String;
+
+    fn into_parser(self) -> Parser<String, E> {
+        primitive::p_str(self.to_string())
+    }
+}
+
+impl<A: 'static, E, S: ?Sized, F> IntoParser<E, S> for F
+where
+    F: Fn(Input<S>) -> Result<(A, Input<S>), E> + 'static,
+{
+    type Output = A;
+
+    fn into_parser(self) -> Parser<A, E, S> {
+        Parser(Rc::new(self))
+    }
+}
+
+impl<A, E, S: Source + ?Sized> Parser<A, E, S> {
+    pub fn run<'i>(&self, inp: &'i S) -> Result<(A, &'i S), E> {
+        let (r, rest) = (self.0)(Input::new(inp))?;
+        Ok((r, rest.rest))
+    }
+
+    // like run, but inp might only be the start of the input, as when it's arriving over
+    // the network. running out of it fails with an error that's incomplete (as in
+    // ParserError::is_incomplete) if more input could still make the parser succeed.
+    // the parser can be run again once more has arrived
+    pub fn run_streaming<'i>(&self, inp: &'i S) -> Result<(A, &'i S), E> {
+        let inp = Input {
+            streaming: true,
+            ..Input::new(inp)
+        };
+        let (r, rest) = (self.0)(inp)?;
+        Ok((r, rest.rest))
+    }
+
+    // like run, but the input is called name, as in the results of `located`
+    pub fn run_named<'i>(&self, name: &str, inp: &'i S) -> Result<(A, &'i S), E> {
+        let named = Input {
+            name: Some(name),
+            ..Input::new(inp)
+        };
+        // rest can't outlive name, so what's left is found by offset instead
+        let (r, rest) = (self.0)(named)?;
+        Ok((r, &inp[rest.offset..]))
+    }
+
+    // like run, with state for get_state, set_state and update_state to work with.
+    // the state is given back as it was left, whether parsing succeeded or not
+    pub fn run_with_state<'i, T: 'static>(
+        &self,
+        inp: &'i S,
+        state: T,
+    ) -> (Result<(A, &'i S), E>, T) {
+        let state = RefCell::new(state);
+        let result = (self.0)(Input {
+            state: Some(&state),
+            ..Input::new(inp)
+        });
+        let result = result.map(|(r, rest)| (r, rest.offset));
+        (result.map(|(r, end)| (r, &inp[end..])), state.into_inner())
+    }
+
+    // like run, but errors are recovered from wherever `recover_with` allows it, and they're
+    // returned along with the result and any warnings, in the order they were found. there's
+    // only no result if parsing failed in a way that couldn't be recovered from, in which case
+    // that error comes last
+    pub fn run_collecting<'i>(&self, inp: &'i S) -> (Option<(A, &'i S)>, Vec<Diagnostic<E>>)
+    where
+        E: ParserError + 'static,
+    {
+        self.run_collecting_at_most(inp, usize::MAX)
+    }
+
+    // like run_collecting, but parsing stops once limit diagnostics have been found,
+    // with a "too many errors, stopping" error after them
+    pub fn run_collecting_at_most<'i>(
+        &self,
+        inp: &'i S,
+        limit: usize,
+    ) -> (Option<(A, &'i S)>, Vec<Diagnostic<E>>)
+    where
+        E: ParserError + 'static,
+    {
+        let log = DiagnosticLog::new(limit);
+        // the log only lives in here, so what's left is found by offset instead
+        let result = (self.0)(Input::collecting(inp, &log));
+        let result = result.map(|(r, rest)| (r, rest.offset));
+        let mut diagnostics = log.into_inner();
+        match result {
+            Ok((r, end)) => (Some((r, &inp[end..])), diagnostics),
+            // a cut doesn't mean anything once parsing is over
+            Err(e) => {
+                diagnostics.push(Diagnostic::error(e.uncut()));
+                (None, diagnostics)
+            }
+        }
+    }
 }
 
-struct Parser<A, E>(Box<dyn Fn(&str) -> Result<(A, &str), E>>);
+// for code that just wants what was parsed
+impl<A, E: ParserError + 'static, S: Source + ?Sized> Parser<A, E, S> {
+    // the result of parsing all of inp, failing if any of it is left over
+    pub fn parse(&self, inp: &S) -> Result<A, E> {
+        let (r, rest) = (self.0)(Input::new(inp))?;
+        (primitive::eof().0)(rest)?;
+        Ok(r)
+    }
+
+    // the result of parsing the start of inp, and what was left over
+    pub fn parse_partial<'i>(&self, inp: &'i S) -> Result<(A, &'i S), E> {
+        self.run(inp)
+    }
 
-impl<A, E> Parser<A, E> {
-    fn run(self, inp: &str) -> Result<(A, &str), E> {
-        self.0.call((inp,))
+    // like parse_partial, starting at offset into inp. offsets and positions, in errors and
+    // otherwise, still count from the start of inp, so it can be shown where things went wrong.
+    // fails without parsing anything if offset is past the end, or in the middle of a char
+    pub fn parse_at<'i>(&self, inp: &'i S, offset: usize) -> Result<(A, &'i S), E> {
+        if !inp.is_boundary(offset) {
+            return Err(E::from_bad_offset(offset));
+        }
+        let at = Input {
+            rest: &inp[offset..],
+            offset,
+            ..Input::new(inp)
+        };
+        let (r, rest) = (self.0)(at)?;
+        Ok((r, rest.rest))
     }
 }
 
-impl<A: 'static> BitOr for Parser<A, ParseError> {
-    type Output = Parser<A, ParseError>;
+// operators, so grammars read like BNF. they bind the way rust's own do: tightest is !a
+// (not), then a + b (pair), then a >> b (then) and a << b (terminated), then a ^ x (p_as),
+// then a | b (p_or). so `!kw >> ident | p_char('x') ^ 0` is
+// `((!kw) >> ident) | (p_char('x') ^ 0)`. >> and << group left to right, so `a >> b << c`
+// keeps b. clippy wants + in brackets when it's next to >>, << or |, which reads better anyway
+impl<A: 'static, E: ParserError + 'static, S: Source + ?Sized, P: IntoParser<E, S, Output = A>>
+    BitOr<P> for Parser<A, E, S>
+{
+    type Output = Parser<A, E, S>;
 
-    fn bitor(self, rhs: Self) -> Self::Output {
-        p_or(self, rhs)
+    fn bitor(self, rhs: P) -> Self::Output {
+        p_or(self, rhs.into_parser())
     }
 }
 
-impl<A: 'static, B: Copy + 'static> BitXor<B> for Parser<A, ParseError> {
-    type Output = Parser<B, ParseError>;
+impl<A: 'static, B: Clone + 'static, E: 'static, S: Source + ?Sized> BitXor<B> for Parser<A, E, S> {
+    type Output = Parser<B, E, S>;
 
     fn bitxor(self, rhs: B) -> Self::Output {
         p_as(self, rhs)
     }
 }
 
-// like >>=
-fn bind<A: 'static, B: 'static, E: 'static>(
-    a: Parser<A, E>,
-    f: fn(A) -> Parser<B, E>,
-) -> Parser<B, E> {
-    Parser(Box::new(move |inp: &str| {
-        let x: Result<(B, &str), E> = match a.0.call((inp,)) {
-            Ok((res, rest)) => f(res).run((rest)),
-            Err(e) => Err(e),
-        };
-        return x;
-    }))
+impl<A: 'static, E: 'static, S: Source + ?Sized, P: IntoParser<E, S>> Shr<P> for Parser<A, E, S> {
+    type Output = Parser<P::Output, E, S>;
+
+    fn shr(self, rhs: P) -> Self::Output {
+        then(self, rhs.into_parser())
+    }
+}
+
+impl<A: 'static, E: 'static, S: Source + ?Sized, P: IntoParser<E, S>> Shl<P> for Parser<A, E, S> {
+    type Output = Parser<A, E, S>;
+
+    fn shl(self, rhs: P) -> Self::Output {
+        terminated(self, rhs.into_parser())
+    }
+}
+
+impl<A: 'static, E: 'static, S: Source + ?Sized, P: IntoParser<E, S>> Add<P> for Parser<A, E, S> {
+    type Output = Parser<(A, P::Output), E, S>;
+
+    fn add(self, rhs: P) -> Self::Output {
+        pair(self, rhs.into_parser())
+    }
+}
+
+impl<A: 'static, E: ParserError + 'static, S: Source + ?Sized> Not for Parser<A, E, S> {
+    type Output = Parser<(), E, S>;
+
+    fn not(self) -> Self::Output {
+        combinator::not(self)
+    }
+}
+
+// and with a literal on the left, as in `"let" >> ident`. rust only allows these with a
+// parser on the right, and not ! at all, so `!'x'` has to be `!p_char('x')`
+macro_rules! impl_literal_operators {
+    ($($lit:ty),+) => {
+        $(
+            impl<B: 'static, E: ParserError + 'static> Shr<Parser<B, E>> for $lit {
+                type Output = Parser<B, E>;
+
+                fn shr(self, rhs: Parser<B, E>) -> Self::Output {
+                    then(self.into_parser(), rhs)
+                }
+            }
+
+            impl<B: 'static, E: ParserError + 'static> Shl<Parser<B, E>> for $lit {
+                type Output = Parser<<$lit as IntoParser<E>>::Output, E>;
+
+                fn shl(self, rhs: Parser<B, E>) -> Self::Output {
+                    terminated(self.into_parser(), rhs)
+                }
+            }
+
+            impl<B: 'static, E: ParserError + 'static> Add<Parser<B, E>> for $lit {
+                type Output = Parser<(<$lit as IntoParser<E>>::Output, B), E>;
+
+                fn add(self, rhs: Parser<B, E>) -> Self::Output {
+                    pair(self.into_parser(), rhs)
+                }
+            }
+
+            impl<E: ParserError + 'static> BitOr<Parser<<$lit as IntoParser<E>>::Output, E>> for $lit {
+                type Output = Parser<<$lit as IntoParser<E>>::Output, E>;
+
+                fn bitor(self, rhs: Parser<<$lit as IntoParser<E>>::Output, E>) -> Self::Output {
+                    p_or(self.into_parser(), rhs)
+                }
+            }
+        )+
+    };
+}
+
+impl_literal_operators!(char, &'static str);
+
+// runs `parser` over and over on the input, yielding each result as it goes.
+// stops at the end of the input, after the first error, or once the parser stops consuming.
+// each result is parsed in a run of its own, so memoize only remembers the one being parsed
+pub struct ParseIter<'a, A, E, S: ?Sized = str> {
+    parser: Parser<A, E, S>,
+    inp: Input<'a, S>,
+    done: bool,
+}
+
+impl<'a, A, E, S: Source + ?Sized> ParseIter<'a, A, E, S> {
+    // the input that hasn't been parsed yet
+    pub fn remaining(&self) -> &'a S {
+        self.inp.rest
+    }
+}
+
+impl<A, E, S: Source + ?Sized> Iterator for ParseIter<'_, A, E, S> {
+    type Item = Result<A, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.inp.rest.len() == 0 {
+            return None;
+        }
+        match (self.parser.0)(self.inp.new_run()) {
+            Ok((r, rest)) => {
+                self.done = rest.offset == self.inp.offset;
+                self.inp = rest;
+                Some(Ok(r))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+pub fn parse_iter<A, E, S: Source + ?Sized>(
+    parser: Parser<A, E, S>,
+    inp: &S,
+) -> ParseIter<'_, A, E, S> {
+    ParseIter {
+        parser,
+        inp: Input::new(inp),
+        done: false,
+    }
+}
+
+// what ReadIter can parse from the bytes it reads
+pub trait FromBytes: Source {
+    // as much of the start of bytes as makes a whole Self, or None if they're not valid
+    fn from_bytes(bytes: &[u8]) -> Option<&Self>;
+}
+
+impl FromBytes for str {
+    // a char split across reads is left for when the rest of it has been read
+    fn from_bytes(bytes: &[u8]) -> Option<&str> {
+        match core::str::from_utf8(bytes) {
+            Ok(s) => Some(s),
+            Err(e) if e.error_len().is_none() => {
+                core::str::from_utf8(&bytes[..e.valid_up_to()]).ok()
+            }
+            Err(_) => None,
+        }
+    }
+}
+
+impl FromBytes for [u8] {
+    fn from_bytes(bytes: &[u8]) -> Option<&[u8]> {
+        Some(bytes)
+    }
+}
+
+// like ParseIter, but over what's read from reader, a bit at a time. the parser runs in
+// streaming mode and more is read whenever it fails with an error that is_incomplete.
+// input that's been parsed is dropped, so all of it never has to be in memory at once
+#[cfg(feature = "std")]
+pub struct ReadIter<R, A, E, S: ?Sized = str> {
+    parser: Parser<A, E, S>,
+    reader: R,
+    buf: Vec<u8>,
+    // how much of the input has been dropped from the front of buf
+    offset: usize,
+    eof: bool,
+    done: bool,
+}
+
+// what to do after parsing as much as possible of what's been read
+#[cfg(feature = "std")]
+enum Step<T> {
+    Done(Option<T>),
+    Fill,
+}
+
+#[cfg(feature = "std")]
+impl<R, A, E, S: ?Sized> ReadIter<R, A, E, S> {
+    fn new(parser: Parser<A, E, S>, reader: R) -> ReadIter<R, A, E, S> {
+        ReadIter {
+            parser,
+            reader,
+            buf: Vec::new(),
+            offset: 0,
+            eof: false,
+            done: false,
+        }
+    }
+
+    // adds a chunk that was read, where nothing means the reader has ended
+    fn filled(&mut self, chunk: &[u8]) {
+        if chunk.is_empty() {
+            self.eof = true;
+        }
+        self.buf.extend_from_slice(chunk);
+    }
+
+    fn failed<T>(&mut self, e: ReadError<E>) -> Step<Result<T, ReadError<E>>> {
+        self.done = true;
+        Step::Done(Some(Err(e)))
+    }
 }
 
-// like *>
-fn then<A: 'static, B: 'static, E: 'static>(a: Parser<A, E>, b: Parser<B, E>) -> Parser<B, E> {
-    Parser(Box::new(move |inp: &str| {
-        let x: Result<(B, &str), E> = match a.0.call((inp,)) {
-            Ok((_, rest)) => b.0.call((rest,)),
-            Err(e) => Err(e),
+#[cfg(feature = "std")]
+impl<R, A, E: ParserError, S: FromBytes + ?Sized> ReadIter<R, A, E, S> {
+    // parses the next item out of buf, unless more has to be read first
+    fn step(&mut self) -> Step<Result<A, ReadError<E>>> {
+        if self.done {
+            return Step::Done(None);
+        }
+        let Some(rest) = S::from_bytes(&self.buf) else {
+            let e = io::Error::new(io::ErrorKind::InvalidData, "input isn't valid");
+            return self.failed(ReadError::Io(e));
         };
-        return x;
-    }))
+        if self.eof && rest.len() < self.buf.len() {
+            let e = io::Error::new(io::ErrorKind::UnexpectedEof, "input ended part way through");
+            return self.failed(ReadError::Io(e));
+        }
+        if rest.len() == 0 {
+            return if self.eof {
+                Step::Done(None)
+            } else {
+                Step::Fill
+            };
+        }
+        let inp = Input {
+            offset: self.offset,
+            src_offset: self.offset,
+            streaming: !self.eof,
+            ..Input::new(rest)
+        };
+        match (self.parser.0)(inp) {
+            Ok((r, rest)) => {
+                let used = rest.offset - self.offset;
+                self.done = used == 0;
+                self.buf.drain(..used);
+                self.offset += used;
+                Step::Done(Some(Ok(r)))
+            }
+            Err(e) if e.is_incomplete() && !self.eof => Step::Fill,
+            Err(e) => self.failed(ReadError::Parse(e)),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read, A, E, S: ?Sized> ReadIter<R, A, E, S> {
+    // reads the next chunk onto the end of buf
+    fn fill(&mut self) -> io::Result<()> {
+        let mut chunk = [0; 8192];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(n) => self.filled(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            return Ok(());
+        }
+    }
 }
 
-// like <$>
-fn map<A: 'static, B: 'static, E: 'static>(a: Parser<A, E>, f: fn(A) -> B) -> Parser<B, E> {
-    Parser(Box::new(move |inp: &str| match a.0.call((inp,)) {
-        Ok((r, remaining)) => Ok((f.call((r,)), remaining)),
-        Err(e) => Err(e),
-    }))
+#[cfg(feature = "std")]
+impl<R: Read, A, E: ParserError, S: FromBytes + ?Sized> Iterator for ReadIter<R, A, E, S> {
+    type Item = Result<A, ReadError<E>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.step() {
+                Step::Done(r) => return r,
+                Step::Fill => {
+                    if let Err(e) = self.fill() {
+                        self.done = true;
+                        return Some(Err(ReadError::Io(e)));
+                    }
+                }
+            }
+        }
+    }
 }
 
-// like $>
-fn p_as<A: 'static, B: 'static + Copy, E: 'static>(a: Parser<A, E>, b: B) -> Parser<B, E> {
-    Parser(Box::new(move |inp: &str| match a.0.call((inp,)) {
-        Ok((r, remaining)) => Ok((b, remaining)),
-        Err(e) => Err(e),
-    }))
+#[cfg(feature = "std")]
+pub fn parse_reader<R: Read, A, E, S: FromBytes + ?Sized>(
+    parser: Parser<A, E, S>,
+    reader: R,
+) -> ReadIter<R, A, E, S> {
+    ReadIter::new(parser, reader)
 }
 
-// primitives
+// reads text held in pieces, like a rope, one piece after another
+#[cfg(feature = "std")]
+pub struct ChunkReader<I: Iterator> {
+    chunks: I,
+    // what's left of the chunk being read
+    chunk: Option<I::Item>,
+    read: usize,
+}
 
-fn p_char(c: char) -> Parser<char, ParseError> {
-    Parser(Box::new(move |inp: &str| {
-        let mut chars = inp.chars();
-        let next = chars.next();
-        match next {
-            Some(c_) if c_ == c => Ok((c, chars.as_str())),
-            Some(wrong) => Err(ParseError::Mismatch(c.to_string(), wrong.to_string())),
-            None => Err(ParseError::EOF(c.to_string())),
+#[cfg(feature = "std")]
+impl<I: Iterator<Item: AsRef<[u8]>>> Read for ChunkReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(chunk) = &self.chunk else {
+                return Ok(0);
+            };
+            let left = &chunk.as_ref()[self.read..];
+            if left.is_empty() {
+                self.chunk = self.chunks.next();
+                self.read = 0;
+                continue;
+            }
+            let n = left.len().min(buf.len());
+            buf[..n].copy_from_slice(&left[..n]);
+            self.read += n;
+            return Ok(n);
         }
-    }))
+    }
+}
+
+// like parse_reader, over input in chunks that aren't next to each other in memory, like
+// the chunks of a rope in an editor. only what hasn't been parsed yet is copied out of them,
+// so the whole input is never put back together in one place
+#[cfg(feature = "std")]
+pub fn parse_chunks<I: IntoIterator<Item: AsRef<[u8]>>, A, E, S: FromBytes + ?Sized>(
+    parser: Parser<A, E, S>,
+    chunks: I,
+) -> ReadIter<ChunkReader<I::IntoIter>, A, E, S> {
+    let mut chunks = chunks.into_iter();
+    let chunk = chunks.next();
+    let reader = ChunkReader {
+        chunks,
+        chunk,
+        read: 0,
+    };
+    parse_reader(parser, reader)
 }
 
-fn p_str(s: String) -> Parser<String, ParseError> {
-    Parser(Box::new(move |inp: &str| {
-        match inp.strip_prefix(&s.to_string()) {
-            Some(remaining) => Ok((s.to_string(), remaining)),
-            None => Err(ParseError::Mismatch(s.to_string(), inp.to_string())),
+#[cfg(feature = "async")]
+impl<R: AsyncRead + Unpin, A, E: ParserError, S: FromBytes + ?Sized> ReadIter<R, A, E, S> {
+    // like Iterator::next, but waits for more input to arrive instead of blocking on it
+    pub async fn next_async(&mut self) -> Option<Result<A, ReadError<E>>> {
+        loop {
+            match self.step() {
+                Step::Done(r) => return r,
+                Step::Fill => {
+                    if let Err(e) = self.fill_async().await {
+                        self.done = true;
+                        return Some(Err(ReadError::Io(e)));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn fill_async(&mut self) -> io::Result<()> {
+        let mut chunk = [0; 8192];
+        loop {
+            let read = poll_fn(|cx| Pin::new(&mut self.reader).poll_read(cx, &mut chunk)).await;
+            match read {
+                Ok(n) => self.filled(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            return Ok(());
         }
-    }))
+    }
 }
 
-fn p_or<A: 'static>(
-    left: Parser<A, ParseError>,
-    right: Parser<A, ParseError>,
-) -> Parser<A, ParseError> {
-    Parser(Box::new(move |inp: &str| {
-        // try left branch
-        match left.0.call((inp,)) {
-            Ok(a) => Ok(a),
-            Err(e) => {
-                // try right branch
-                match right.0.call((inp,)) {
-                    Ok(b) => Ok(b),
-                    Err(e2) => Err(ParseError::Multiple(vec![e, e2])),
+// like parse_reader, for reading with next_async. reader is the AsyncRead of futures, which
+// tokio's readers can be made into with tokio-util's compat
+#[cfg(feature = "async")]
+pub fn parse_async_reader<R: AsyncRead + Unpin, A, E, S: FromBytes + ?Sized>(
+    parser: Parser<A, E, S>,
+    reader: R,
+) -> ReadIter<R, A, E, S> {
+    ReadIter::new(parser, reader)
+}
+
+// reads what a stream of chunks gives, like the body of a response, as it arrives
+#[cfg(feature = "async")]
+pub struct StreamReader<St, B> {
+    stream: St,
+    // what's left of the chunk being read
+    chunk: Option<B>,
+    read: usize,
+}
+
+#[cfg(feature = "async")]
+impl<St, B> AsyncRead for StreamReader<St, B>
+where
+    St: Stream<Item = io::Result<B>> + Unpin,
+    B: AsRef<[u8]> + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(chunk) = &this.chunk {
+                let left = &chunk.as_ref()[this.read..];
+                if !left.is_empty() {
+                    let n = left.len().min(buf.len());
+                    buf[..n].copy_from_slice(&left[..n]);
+                    this.read += n;
+                    return Poll::Ready(Ok(n));
+                }
+            }
+            match Pin::new(&mut this.stream).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    this.chunk = Some(chunk);
+                    this.read = 0;
                 }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(0)),
+                Poll::Pending => return Poll::Pending,
             }
         }
-    }))
+    }
+}
+
+// like parse_chunks, but the chunks come from stream as they arrive, for reading with
+// next_async. an error from the stream ends parsing with it
+#[cfg(feature = "async")]
+pub fn parse_stream<St, B, A, E, S: FromBytes + ?Sized>(
+    parser: Parser<A, E, S>,
+    stream: St,
+) -> ReadIter<StreamReader<St, B>, A, E, S>
+where
+    St: Stream<Item = io::Result<B>> + Unpin,
+    B: AsRef<[u8]> + Unpin,
+{
+    let reader = StreamReader {
+        stream,
+        chunk: None,
+        read: 0,
+    };
+    parse_async_reader(parser, reader)
+}
+
+// a file mapped into memory, so it can be parsed without reading all of it in first
+#[cfg(feature = "mmap")]
+pub struct MappedFile(memmap2::Mmap);
+
+#[cfg(feature = "mmap")]
+impl MappedFile {
+    /// maps the file at path into memory to be read.
+    ///
+    /// # Safety
+    ///
+    /// the file mustn't be changed or truncated while it's mapped, by this process or any
+    /// other. what's mapped is the file itself, so the bytes that as_bytes gives would change
+    /// under whatever is holding them, or stop being there at all
+    pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<MappedFile> {
+        let file = File::open(path)?;
+        // the mapping stays valid after the file is closed
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Ok(MappedFile(map))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<A, E: ParserError + 'static, S: FromBytes + ?Sized> Parser<A, E, S> {
+    /// parses all of the file at path, straight out of memory it's mapped to. the file is
+    /// given back with the result, so spans in it can be looked up with Span::of.
+    ///
+    /// # Safety
+    ///
+    /// the same as for MappedFile::open: the file mustn't change while it's mapped
+    pub unsafe fn run_file(&self, path: impl AsRef<Path>) -> Result<(A, MappedFile), ReadError<E>> {
+        let file = unsafe { MappedFile::open(path) }.map_err(ReadError::Io)?;
+        let bytes = file.as_bytes();
+        let src = match S::from_bytes(bytes) {
+            Some(src) if src.len() == bytes.len() => src,
+            _ => {
+                let e = io::Error::new(io::ErrorKind::InvalidData, "input isn't valid");
+                return Err(ReadError::Io(e));
+            }
+        };
+        let (r, rest) = (self.0)(Input::new(src)).map_err(ReadError::Parse)?;
+        (primitive::eof().0)(rest).map_err(ReadError::Parse)?;
+        Ok((r, file))
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::error::Error;
+    use std::ops::Range;
+
     use super::*;
+    use crate::primitive::*;
 
     #[test]
     fn test_scheme() {
         #[derive(PartialEq, Debug, Clone, Copy)]
+        #[allow(clippy::upper_case_acronyms)]
         enum Scheme {
             HTTP,
             HTTPS,
         }
-        let scheme = (p_str("https".to_string()) ^ Scheme::HTTPS)
+        let scheme = (p_str::<ParseError>("https".to_string()) ^ Scheme::HTTPS)
             | (p_str("http".to_string()) ^ Scheme::HTTP);
 
         assert_eq!(Ok(((Scheme::HTTP), "")), scheme.run("http"));
@@ -137,12 +1003,2639 @@ mod tests {
     }
 
     #[test]
+    #[allow(unused_parens, clippy::double_parens)]
     fn it_works() {
-        let char = p_or(p_char('c'), p_char('h'));
+        let char = p_or(p_char::<ParseError>('c'), p_char('h'));
         assert_eq!(Ok(('h', "ello")), char.run(("hello")));
         assert_eq!(Ok(('c', "ello")), char.run(("cello")));
 
         let full = then(char, p_str("ello".to_string())).run(("hello"));
         println!("{:?}", full)
     }
+
+    #[test]
+    fn test_many() {
+        assert_eq!(
+            Ok((vec![], "bc")),
+            many0(p_char::<ParseError>('a')).run("bc")
+        );
+        assert_eq!(
+            Ok((vec!['a', 'a'], "bc")),
+            many0(p_char::<ParseError>('a')).run("aabc")
+        );
+
+        assert_eq!(
+            Ok((vec!['a', 'a'], "bc")),
+            many1(p_char::<ParseError>('a')).run("aabc")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Literal("a".to_string()),
+                "b".to_string()
+            )),
+            many1(p_char('a')).run("bc")
+        );
+    }
+
+    #[test]
+    fn test_sep_by() {
+        assert_eq!(
+            Ok((vec!['a', 'a', 'a'], "")),
+            sep_by(p_char::<ParseError>('a'), p_char(',')).run("a,a,a")
+        );
+        assert_eq!(
+            Ok((vec![], "b")),
+            sep_by(p_char::<ParseError>('a'), p_char(',')).run("b")
+        );
+        // the trailing separator is not consumed
+        assert_eq!(
+            Ok((vec!['a', 'a'], ",b")),
+            sep_by(p_char::<ParseError>('a'), p_char(',')).run("a,a,b")
+        );
+
+        assert_eq!(
+            Ok((vec!['a'], ",")),
+            sep_by1(p_char::<ParseError>('a'), p_char(',')).run("a,")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Literal("a".to_string()))),
+            sep_by1(p_char('a'), p_char(',')).run("")
+        );
+    }
+
+    #[test]
+    fn test_opt() {
+        assert_eq!(
+            Ok((Some('-'), "1")),
+            opt(p_char::<ParseError>('-')).run("-1")
+        );
+        assert_eq!(Ok((None, "1")), opt(p_char::<ParseError>('-')).run("1"));
+    }
+
+    #[test]
+    fn test_count() {
+        assert_eq!(
+            Ok((vec!['a', 'a'], "a")),
+            count(2, p_char::<ParseError>('a')).run("aaa")
+        );
+        assert_eq!(
+            Ok((vec![], "aaa")),
+            count(0, p_char::<ParseError>('a')).run("aaa")
+        );
+        assert_eq!(
+            Err(ParseError::Repetition(
+                2,
+                Box::new(ParseError::Mismatch(
+                    2..3,
+                    Expected::Literal("a".to_string()),
+                    "b".to_string()
+                ))
+            )),
+            count(3, p_char('a')).run("aab")
+        );
+    }
+
+    #[test]
+    fn test_take_while() {
+        let digits = take_while::<_, ParseError>(|c| c.is_ascii_digit()).run("123abc");
+        assert_eq!(Ok(("123".to_string(), "abc")), digits);
+        let none = take_while::<_, ParseError>(|c| c.is_ascii_digit()).run("abc");
+        assert_eq!(Ok(("".to_string(), "abc")), none);
+
+        let digits = take_while1::<_, ParseError>(|c| c.is_ascii_digit()).run("123");
+        assert_eq!(Ok(("123".to_string(), "")), digits);
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("matching character".to_string()),
+                "a".to_string()
+            )),
+            take_while1(|c| c.is_ascii_digit()).run("abc")
+        );
+    }
+
+    #[test]
+    fn test_take_until() {
+        assert_eq!(
+            Ok((" comment ".to_string(), "*/ rest")),
+            take_until::<ParseError>("*/").run(" comment */ rest")
+        );
+        assert_eq!(
+            Ok(("".to_string(), "*/")),
+            take_until::<ParseError>("*/").run("*/")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(13, Expected::Literal("*/".to_string()))),
+            take_until("*/").run(" unterminated")
+        );
+    }
+
+    #[test]
+    fn test_between() {
+        let quoted = between(
+            p_char::<ParseError>('"'),
+            take_while(|c| c != '"'),
+            p_char('"'),
+        );
+        assert_eq!(Ok(("hi".to_string(), "!")), quoted.run("\"hi\"!"));
+
+        let unclosed = between(p_char('('), p_char('a'), p_char(')')).run("(a");
+        assert_eq!(
+            Err(ParseError::EOF(2, Expected::Literal(")".to_string()))),
+            unclosed
+        );
+    }
+
+    #[test]
+    fn test_preceded_terminated() {
+        let neg = preceded(p_char::<ParseError>('-'), p_char('1')).run("-1");
+        assert_eq!(Ok(('1', "")), neg);
+
+        let stmt = terminated(
+            take_while1(|c| c.is_alphabetic()),
+            p_char::<ParseError>(';'),
+        )
+        .run("x;y");
+        assert_eq!(Ok(("x".to_string(), "y")), stmt);
+        let missing = terminated(p_char('x'), p_char(';')).run("x");
+        assert_eq!(
+            Err(ParseError::EOF(1, Expected::Literal(";".to_string()))),
+            missing
+        );
+    }
+
+    #[test]
+    fn test_pair() {
+        let kv = pair(
+            p_char::<ParseError>('k'),
+            preceded(p_char('='), p_char('v')),
+        )
+        .run("k=v");
+        assert_eq!(Ok((('k', 'v'), "")), kv);
+        let missing = pair(p_char('k'), p_char('v')).run("kx");
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..2,
+                Expected::Literal("v".to_string()),
+                "x".to_string()
+            )),
+            missing
+        );
+    }
+
+    #[test]
+    fn test_choice() {
+        let op = || choice(vec![p_char('+'), p_char('-'), p_char('*') | p_char('/')]);
+        assert_eq!(Ok(('-', "1")), op().run("-1"));
+        assert_eq!(Ok(('/', "1")), op().run("/1"));
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Literal("+".to_string()),
+                    Expected::Literal("-".to_string()),
+                    Expected::Literal("*".to_string()),
+                    Expected::Literal("/".to_string())
+                ],
+                Some("1".to_string())
+            )),
+            op().run("1")
+        );
+    }
+
+    #[test]
+    fn test_permutation() {
+        let abc = || permutation((p_char('a'), p_char('b'), p_char('c')));
+        assert_eq!(Ok((('a', 'b', 'c'), "")), abc().run("abc"));
+        assert_eq!(Ok((('a', 'b', 'c'), "!")), abc().run("cab!"));
+        assert_eq!(
+            Err(ParseError::Expected(
+                1,
+                vec![
+                    Expected::Literal("a".to_string()),
+                    Expected::Literal("c".to_string())
+                ],
+                Some("b".to_string())
+            )),
+            abc().run("bb")
+        );
+    }
+
+    #[test]
+    fn test_fold_many() {
+        let number = || {
+            map(take_while1(|c| c.is_ascii_digit()), |s| {
+                s.parse::<u32>().unwrap()
+            })
+        };
+        assert_eq!(
+            Ok((356, "")),
+            fold_many0(
+                terminated(number(), p_char::<ParseError>(' ')),
+                || 0,
+                |acc, n| acc + n
+            )
+            .run("1 22 333 ")
+        );
+        assert_eq!(
+            Ok((0, "x")),
+            fold_many0(number(), || 0, |acc, n| acc + n).run("x")
+        );
+
+        let sum = fold_many1(p_char::<ParseError>('1'), || 0, |acc, _| acc + 1).run("111");
+        assert_eq!(Ok((3, "")), sum);
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Literal("1".to_string()))),
+            fold_many1(p_char('1'), || 0, |acc, _| acc + 1).run("")
+        );
+    }
+
+    #[test]
+    fn test_repeat() {
+        let a = || p_char('a');
+        assert_eq!(Ok((vec!['a', 'a'], "")), repeat(a(), 2..=4).run("aa"));
+        assert_eq!(Ok((vec!['a'; 4], "a")), repeat(a(), 2..=4).run("aaaaa"));
+        assert_eq!(
+            Err(ParseError::Repetition(
+                1,
+                Box::new(ParseError::EOF(1, Expected::Literal("a".to_string())))
+            )),
+            repeat(a(), 2..=4).run("a")
+        );
+        assert_eq!(Ok((vec!['a'; 2], "a")), repeat(a(), 2..=2).run("aaa"));
+    }
+
+    #[test]
+    #[should_panic(expected = "repeat range is empty")]
+    fn test_repeat_empty_range() {
+        // bounds worked out at runtime, where nothing catches them being the wrong way round
+        let (min, max) = (3, 2);
+        let _ = repeat(p_char::<ParseError>('a'), min..=max);
+    }
+
+    #[test]
+    fn test_peek() {
+        assert_eq!(Ok(('a', "abc")), peek(p_char::<ParseError>('a')).run("abc"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Literal("a".to_string()),
+                "x".to_string()
+            )),
+            peek(p_char('a')).run("xbc")
+        );
+    }
+
+    #[test]
+    fn test_not() {
+        assert_eq!(
+            Ok(((), "/")),
+            not(p_str::<ParseError>("*/".to_string())).run("/")
+        );
+        assert_eq!(
+            Err(ParseError::Unexpected(0, "*/".to_string())),
+            not(p_str("*/".to_string())).run("*/")
+        );
+    }
+
+    #[test]
+    fn test_recognize() {
+        let number = recognize(pair(
+            opt(p_char::<ParseError>('-')),
+            take_while1(|c| c.is_ascii_digit()),
+        ));
+        assert_eq!(Ok(("-12".to_string(), "+3")), number.run("-12+3"));
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Literal("a".to_string()))),
+            recognize(many1(p_char('a'))).run("")
+        );
+    }
+
+    #[test]
+    fn test_consumed() {
+        let ab = consumed(pair(p_char::<ParseError>('a'), p_char('b'))).run("abc");
+        assert_eq!(Ok(((('a', 'b'), "ab".to_string()), "c")), ab);
+    }
+
+    #[test]
+    fn test_eof() {
+        assert_eq!(
+            Ok(('a', "")),
+            terminated(p_char::<ParseError>('a'), eof()).run("a")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..2,
+                Expected::EndOfInput,
+                "b".to_string()
+            )),
+            terminated(p_char('a'), eof()).run("ab")
+        );
+        // when streaming, more might be on its way
+        assert_eq!(
+            Err(ParseError::Incomplete(1, Needed::Unknown)),
+            terminated(p_char('a'), eof()).run_streaming("a")
+        );
+    }
+
+    #[test]
+    fn test_rest() {
+        let payload = preceded(p_str::<ParseError>("DATA ".to_string()), rest()).run("DATA 1 2 3");
+        assert_eq!(Ok(("1 2 3".to_string(), "")), payload);
+        let payload = preceded(p_str("DATA ".to_string()), rest()).run_streaming("DATA 1");
+        assert_eq!(Err(ParseError::Incomplete(6, Needed::Unknown)), payload);
+    }
+
+    #[test]
+    fn test_verify() {
+        let octet = || {
+            verify(
+                map(take_while1(|c| c.is_ascii_digit()), |s| {
+                    s.parse::<u32>().unwrap()
+                }),
+                |n| *n < 256,
+            )
+        };
+        assert_eq!(Ok((255, ".")), octet().run("255."));
+        assert_eq!(
+            Err(ParseError::Invalid(0, "256".to_string())),
+            octet().run("256.")
+        );
+    }
+
+    #[test]
+    fn test_closures() {
+        let offset = 10;
+        let shifted = map(p_char::<ParseError>('1'), move |c| {
+            c.to_digit(10).unwrap() + offset
+        })
+        .run("1");
+        assert_eq!(Ok((11, "")), shifted);
+
+        let delim = ',';
+        let field = take_while1::<_, ParseError>(move |c| c != delim).run("ab,c");
+        assert_eq!(Ok(("ab".to_string(), ",c")), field);
+    }
+
+    #[test]
+    fn test_bind() {
+        // the closing char depends on the opening one
+        let content = 'x';
+        let bracketed = bind(p_char::<ParseError>('(') | p_char('['), move |open| {
+            let close = if open == '(' { ')' } else { ']' };
+            map(then(p_char(content), p_char(close)), move |_| open)
+        });
+        assert_eq!(Ok(('[', "")), bracketed.run("[x]"));
+    }
+
+    #[test]
+    fn test_map_res() {
+        let byte = || map_res(take_while1(|c| c.is_ascii_digit()), |s| s.parse::<u8>());
+        assert_eq!(Ok((255, "")), byte().run("255"));
+        assert_eq!(
+            Err(ParseError::Conversion(
+                0,
+                "256".to_string(),
+                "number too large to fit in target type".to_string()
+            )),
+            byte().run("256")
+        );
+    }
+
+    #[test]
+    fn test_map_opt() {
+        #[derive(PartialEq, Debug)]
+        enum Keyword {
+            If,
+            Else,
+        }
+        let keyword = || {
+            map_opt(take_while1(|c| c.is_alphabetic()), |s| match s.as_str() {
+                "if" => Some(Keyword::If),
+                "else" => Some(Keyword::Else),
+                _ => None,
+            })
+        };
+        assert_eq!(Ok((Keyword::Else, " {")), keyword().run("else {"));
+        assert_eq!(
+            Err(ParseError::Invalid(0, "when".to_string())),
+            keyword().run("when {")
+        );
+    }
+
+    #[test]
+    fn test_cond() {
+        let signed = bind(p_char::<ParseError>('s') | p_char('u'), |kind| {
+            cond(kind == 's', p_char('-'))
+        });
+        assert_eq!(Ok((Some('-'), "1")), signed.run("s-1"));
+        let unsigned = bind(p_char::<ParseError>('s') | p_char('u'), |kind| {
+            cond(kind == 's', p_char('-'))
+        });
+        assert_eq!(Ok((None, "-1")), unsigned.run("u-1"));
+    }
+
+    #[test]
+    fn test_pure_fail() {
+        assert_eq!(Ok((1, "abc")), pure::<_, ParseError, _>(1).run("abc"));
+
+        let even = bind(take_while1(|c| c.is_ascii_digit()), |s| {
+            let n = s.parse::<u32>().unwrap();
+            if n % 2 == 0 {
+                pure(n)
+            } else {
+                fail(ParseError::Invalid(0, s))
+            }
+        });
+        assert_eq!(Err(ParseError::Invalid(0, "7".to_string())), even.run("7"));
+    }
+
+    #[test]
+    fn test_as() {
+        let null = p_str::<ParseError>("nil".to_string()) ^ "null".to_string();
+        assert_eq!(Ok(("null".to_string(), "")), null.run("nil"));
+
+        let empty = map_to_with(p_str::<ParseError>("[]".to_string()), Vec::<u32>::new);
+        assert_eq!(Ok((vec![], "")), empty.run("[]"));
+    }
+
+    #[test]
+    fn test_lazy() {
+        // nested = '(' nested ')' | 'x'
+        fn nested() -> Parser<usize, ParseError> {
+            between(
+                p_char('('),
+                map(lazy(nested), |depth| depth + 1),
+                p_char(')'),
+            ) | (p_char('x') ^ 0)
+        }
+        assert_eq!(Ok((0, "")), nested().run("x"));
+        assert_eq!(Ok((3, "")), nested().run("(((x)))"));
+        assert_eq!(
+            Err(ParseError::EOF(2, Expected::Literal(")".to_string()))),
+            nested().run("(x")
+        );
+    }
+
+    #[test]
+    fn test_recursive() {
+        // list = '[' (list (',' list)*)? ']'
+        let list: Parser<usize, ParseError> = recursive(|list| {
+            map(
+                between(p_char('['), sep_by(list, p_char(',')), p_char(']')),
+                |items| items.len(),
+            )
+        });
+        assert_eq!(Ok((3, "")), list.run("[[],[[]],[]]"));
+    }
+
+    #[test]
+    fn test_many_till() {
+        let block = || many_till(p_char('a'), p_str("end".to_string()));
+        assert_eq!(
+            Ok(((vec!['a', 'a'], "end".to_string()), "!")),
+            block().run("aaend!")
+        );
+        assert_eq!(Ok(((vec![], "end".to_string()), "")), block().run("end"));
+        assert_eq!(
+            Err(ParseError::Expected(
+                1,
+                vec![
+                    Expected::Literal("end".to_string()),
+                    Expected::Literal("a".to_string())
+                ],
+                Some("b".to_string())
+            )),
+            block().run("ab")
+        );
+    }
+
+    #[test]
+    fn test_length_count() {
+        let len = || map_res(take_while1(|c| c.is_ascii_digit()), |s| s.parse::<usize>());
+        let field = || length_count(terminated(len(), p_char(':')), p_char('a'));
+        assert_eq!(Ok((vec!['a'; 3], "a")), field().run("3:aaaa"));
+        assert_eq!(
+            Err(ParseError::Repetition(
+                2,
+                Box::new(ParseError::EOF(4, Expected::Literal("a".to_string())))
+            )),
+            field().run("3:aa")
+        );
+    }
+
+    #[test]
+    fn test_parse_iter() {
+        let line = terminated(take_while1(|c| c != '\n'), p_char('\n'));
+        let mut lines = parse_iter(line, "a\nbc\nd");
+        assert_eq!(Some(Ok("a".to_string())), lines.next());
+        assert_eq!(Some(Ok("bc".to_string())), lines.next());
+        assert_eq!(
+            Some(Err(ParseError::EOF(6, Expected::Literal("\n".to_string())))),
+            lines.next()
+        );
+        assert_eq!(None, lines.next());
+        assert_eq!("d", lines.remaining());
+    }
+
+    #[test]
+    fn test_all_consuming() {
+        let scheme = || all_consuming(p_str("https".to_string()) | p_str("http".to_string()));
+        assert_eq!(Ok(("https".to_string(), "")), scheme().run("https"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                5..12,
+                Expected::EndOfInput,
+                "garbage".to_string()
+            )),
+            scheme().run("httpsgarbage")
+        );
+    }
+
+    #[test]
+    fn test_cut() {
+        let stmt = || {
+            then(p_str("let ".to_string()), cut(p_char('x')))
+                | map(p_str("letter".to_string()), |_| 'l')
+        };
+        assert_eq!(Ok(('x', "")), stmt().run("let x"));
+        // the second branch isn't tried once "let " has matched
+        assert_eq!(
+            Err(ParseError::Cut(Box::new(ParseError::Mismatch(
+                4..5,
+                Expected::Literal("x".to_string()),
+                "y".to_string()
+            )))),
+            stmt().run("let y")
+        );
+
+        // repetition doesn't swallow a cut either
+        let items = many0(then(p_char('-'), cut(p_char('a')))).run("-a-b");
+        assert_eq!(
+            Err(ParseError::Cut(Box::new(ParseError::Mismatch(
+                3..4,
+                Expected::Literal("a".to_string()),
+                "b".to_string()
+            )))),
+            items
+        );
+    }
+
+    #[test]
+    fn test_attempt() {
+        let stmt = || {
+            attempt(then(p_str("let ".to_string()), cut(p_char('x'))))
+                | map(p_str("let y".to_string()), |_| 'y')
+        };
+        assert_eq!(Ok(('y', "")), stmt().run("let y"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                4..5,
+                Expected::Literal("x".to_string()),
+                "z".to_string()
+            )),
+            stmt().run("let z")
+        );
+    }
+
+    #[test]
+    fn test_map_parser() {
+        let field = || terminated(take_until(","), p_char(','));
+        let digits = || many1(p_char('1') | p_char('0'));
+        assert_eq!(
+            Ok((vec!['1', '0'], "rest")),
+            map_parser(field(), digits()).run("10,rest")
+        );
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Literal("1".to_string()),
+                    Expected::Literal("0".to_string())
+                ],
+                Some("x".to_string())
+            )),
+            map_parser(field(), digits()).run("x1,rest")
+        );
+
+        // the region ends where outer stopped, not where the whole input does
+        let number = || map_parser(field(), all_consuming(digits()));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                2..3,
+                Expected::EndOfInput,
+                "x".to_string()
+            )),
+            number().run("10x,rest")
+        );
+        let number = map_parser(field(), terminated(digits(), p_char('x')));
+        assert_eq!(
+            Err(ParseError::EOF(2, Expected::Literal("x".to_string()))),
+            number.run("10,rest")
+        );
+    }
+
+    #[test]
+    fn test_satisfy() {
+        let lower = || satisfy(|c| c.is_ascii_lowercase());
+        assert_eq!(Ok(('q', "Z")), lower().run("qZ"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("matching character".to_string()),
+                "Z".to_string()
+            )),
+            lower().run("Zq")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(
+                0,
+                Expected::Class("matching character".to_string())
+            )),
+            lower().run("")
+        );
+    }
+
+    #[test]
+    fn test_any_char() {
+        let escape = preceded(p_char::<ParseError>('\\'), any_char()).run("\\\"x");
+        assert_eq!(Ok(('"', "x")), escape);
+        assert_eq!(
+            Err(ParseError::EOF(
+                0,
+                Expected::Class("any character".to_string())
+            )),
+            any_char().run("")
+        );
+    }
+
+    #[test]
+    fn test_one_of() {
+        assert_eq!(Ok(('*', "2")), one_of::<ParseError>("+-*/").run("*2"));
+        assert_eq!(Ok(('λ', "")), one_of::<ParseError>("αλ").run("λ"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("one of \"+-*/\"".to_string()),
+                "2".to_string()
+            )),
+            one_of("+-*/").run("2")
+        );
+
+        let string_char = || none_of::<ParseError>("\"\\");
+        assert_eq!(Ok(('a', "\"")), string_char().run("a\""));
+        assert!(string_char().run("\\n").is_err());
+        assert_eq!(
+            Err(ParseError::EOF(
+                0,
+                Expected::Class("none of \",;\"".to_string())
+            )),
+            none_of(",;").run("")
+        );
+    }
+
+    #[test]
+    fn test_char_range() {
+        let hex = || char_range('0'..='9') | char_range('a'..='f');
+        assert_eq!(Ok(('c', "")), hex().run("c"));
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Class("'0'..='9'".to_string()),
+                    Expected::Class("'a'..='f'".to_string())
+                ],
+                Some("g".to_string())
+            )),
+            hex().run("g")
+        );
+    }
+
+    #[test]
+    fn test_char_classes() {
+        assert_eq!(Ok(('7', "a")), digit::<ParseError>().run("7a"));
+        assert_eq!(
+            Ok(("123".to_string(), "a")),
+            digit1::<ParseError>().run("123a")
+        );
+        assert_eq!(
+            Ok(("abc".to_string(), "1")),
+            alpha1::<ParseError>().run("abc1")
+        );
+        assert_eq!(
+            Ok(("a1b2".to_string(), " ")),
+            alphanumeric1::<ParseError>().run("a1b2 ")
+        );
+        assert_eq!(
+            Ok((" \t\n".to_string(), "x")),
+            whitespace1::<ParseError>().run(" \t\nx")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("digit".to_string()),
+                "a".to_string()
+            )),
+            digit1().run("a1")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Class("letter".to_string()))),
+            alpha().run("")
+        );
+    }
+
+    #[test]
+    fn test_spaces() {
+        assert_eq!(
+            Ok((" \t".to_string(), "\nx")),
+            space0::<ParseError>().run(" \t\nx")
+        );
+        assert_eq!(Ok(("".to_string(), "x")), space0::<ParseError>().run("x"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("space".to_string()),
+                "\n".to_string()
+            )),
+            space1().run("\n")
+        );
+        assert_eq!(
+            Ok((" \r\n\t".to_string(), "x")),
+            multispace0::<ParseError>().run(" \r\n\tx")
+        );
+        assert_eq!(
+            Ok(("\n".to_string(), "")),
+            multispace1::<ParseError>().run("\n")
+        );
+    }
+
+    #[test]
+    fn test_line_ending() {
+        assert_eq!(Ok(('\n', "x")), newline::<ParseError>().run("\nx"));
+        assert_eq!(
+            Ok(("\r\n".to_string(), "x")),
+            crlf::<ParseError>().run("\r\nx")
+        );
+
+        assert_eq!(
+            Ok(("\n".to_string(), "x")),
+            line_ending::<ParseError>().run("\nx")
+        );
+        assert_eq!(
+            Ok(("\r\n".to_string(), "x")),
+            line_ending::<ParseError>().run("\r\nx")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Label("line ending".to_string()),
+                "\r".to_string()
+            )),
+            line_ending().run("\rx")
+        );
+        // the \n might not have arrived yet
+        assert_eq!(
+            Err(ParseError::Incomplete(1, Needed::Size(1))),
+            line_ending().run_streaming("\r")
+        );
+        assert_eq!(
+            Ok(("\r\n".to_string(), "")),
+            line_ending::<ParseError>().run_streaming("\r\n")
+        );
+    }
+
+    #[test]
+    fn test_p_str_no_case() {
+        assert_eq!(
+            Ok(("SeLeCt".to_string(), " *")),
+            p_str_no_case::<ParseError>("select").run("SeLeCt *")
+        );
+        assert_eq!(
+            Ok(("ÄBC".to_string(), "")),
+            p_str_no_case::<ParseError>("äbc").run("ÄBC")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..3,
+                Expected::Literal("select".to_string()),
+                "sel".to_string()
+            )),
+            p_str_no_case("select").run("sel")
+        );
+        assert_eq!(
+            Err(ParseError::Incomplete(3, Needed::Unknown)),
+            p_str_no_case("select").run_streaming("SEL")
+        );
+    }
+
+    #[test]
+    fn test_int() {
+        assert_eq!(Ok((-42, "px")), int::<i64, ParseError>().run("-42px"));
+        assert_eq!(Ok((7, "")), int::<i8, ParseError>().run("+7"));
+        assert_eq!(
+            Err(ParseError::Conversion(
+                0,
+                "128".to_string(),
+                "number too large to fit in target type".to_string()
+            )),
+            int::<i8, ParseError>().run("128")
+        );
+
+        assert_eq!(Ok((255, ",")), uint::<u8, ParseError>().run("255,"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("digit".to_string()),
+                "-".to_string()
+            )),
+            uint::<u64, ParseError>().run("-1")
+        );
+    }
+
+    #[test]
+    fn test_float() {
+        assert_eq!(Ok((1.5, "")), float::<f64, ParseError>().run("1.5"));
+        assert_eq!(
+            Ok((-0.25e-2, "x")),
+            float::<f64, ParseError>().run("-.25e-2x")
+        );
+        assert_eq!(Ok((1.0, ",")), float::<f64, ParseError>().run("1.,"));
+        // when streaming, a float at the end of the input might go on
+        assert_eq!(
+            Err(ParseError::Incomplete(3, Needed::Unknown)),
+            float::<f64, ParseError>().run_streaming("3.1")
+        );
+        assert_eq!(
+            Err(ParseError::Incomplete(3, Needed::Unknown)),
+            float::<f64, ParseError>().run_streaming("-in")
+        );
+        assert_eq!(
+            Ok((3.1, " ")),
+            float::<f64, ParseError>().run_streaming("3.1 ")
+        );
+        // an exponent without digits isn't part of the number
+        assert_eq!(Ok((2.0, "e+")), float::<f64, ParseError>().run("2e+"));
+        assert_eq!(
+            Ok((f64::NEG_INFINITY, "")),
+            float::<f64, ParseError>().run("-Infinity")
+        );
+        assert!(float::<f32, ParseError>().run("NaN").unwrap().0.is_nan());
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Label("float".to_string()),
+                ".".to_string()
+            )),
+            float::<f64, ParseError>().run(".e1")
+        );
+    }
+
+    #[test]
+    fn test_int_radix() {
+        let color = preceded(p_char::<ParseError>('#'), hex_u32()).run("#ff00aa;");
+        assert_eq!(Ok((0xff00aa, ";")), color);
+        let prefixed = preceded(p_str::<ParseError>("0b".to_string()), bin_u32()).run("0b1012");
+        assert_eq!(Ok((0b101, "2")), prefixed);
+        assert_eq!(Ok((0o17, "")), oct_u32::<ParseError>().run("17"));
+        assert_eq!(Ok((35, "")), int_radix::<ParseError>(36).run("z"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("base 16 digit".to_string()),
+                "g".to_string()
+            )),
+            hex_u32().run("g")
+        );
+        assert_eq!(
+            Err(ParseError::Conversion(
+                0,
+                "100000000".to_string(),
+                "number too large to fit in target type".to_string()
+            )),
+            hex_u32().run("100000000")
+        );
+    }
+
+    #[test]
+    fn test_escaped() {
+        let contents = || escaped(none_of::<ParseError>(r#""\"#), '\\', one_of(r#""\n"#));
+        assert_eq!(
+            Ok((r#"a\"b\n"#.to_string(), "\"")),
+            contents().run(r#"a\"b\n""#)
+        );
+        assert_eq!(Ok(("".to_string(), "\"")), contents().run("\""));
+        // \x isn't a known escape
+        assert!(contents().run(r"a\x").is_err());
+    }
+
+    #[test]
+    fn test_quoted_string() {
+        let string = || quoted_string(QuoteConfig::default());
+        assert_eq!(
+            Ok(("a\"b\n😀".to_string(), "!")),
+            string().run(r#""a\"b\n\u{1F600}"!"#)
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                2..3,
+                Expected::Label("escape sequence".to_string()),
+                "q".to_string()
+            )),
+            string().run(r#""\q""#)
+        );
+        assert_eq!(
+            Err(ParseError::Invalid(3, "{D800}".to_string())),
+            string().run(r#""\u{D800}""#)
+        );
+        assert_eq!(
+            Err(ParseError::EOF(4, Expected::Literal("\"".to_string()))),
+            string().run(r#""abc"#)
+        );
+
+        let single = quoted_string::<ParseError>(QuoteConfig {
+            quotes: vec!['\''],
+            escape_char: '\'',
+            escapes: vec![('\'', '\'')],
+            unicode_escapes: false,
+        });
+        assert_eq!(Ok(("it's".to_string(), "")), single.run("'it''s'"));
+    }
+
+    #[test]
+    fn test_identifier() {
+        assert_eq!(
+            Ok(("_foo1".to_string(), "-")),
+            identifier::<ParseError>().run("_foo1-")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Label("identifier".to_string()),
+                "1".to_string()
+            )),
+            identifier().run("1foo")
+        );
+
+        let lisp: Parser<String, ParseError> =
+            identifier_with(|c| c.is_alphabetic(), |c| c.is_alphanumeric() || c == '-');
+        assert_eq!(Ok(("λ-list".to_string(), " x")), lisp.run("λ-list x"));
+
+        assert_eq!(
+            Err(ParseError::Incomplete(2, Needed::Unknown)),
+            identifier().run_streaming("ab")
+        );
+        assert_eq!(
+            Ok(("ab".to_string(), " ")),
+            identifier::<ParseError>().run_streaming("ab ")
+        );
+    }
+
+    #[test]
+    fn test_take() {
+        let date = pair(take::<ParseError>(4), preceded(p_char('-'), take(2))).run("2024-01-31");
+        assert_eq!(Ok((("2024".to_string(), "01".to_string()), "-31")), date);
+        assert_eq!(Ok(("äö".to_string(), "")), take::<ParseError>(2).run("äö"));
+        assert_eq!(
+            Err(ParseError::EOF(
+                2,
+                Expected::Label("3 characters".to_string())
+            )),
+            take(3).run("ab")
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn test_regex() {
+        let month = regex::<ParseError>(r"[0-9]{4}-[0-9]{2}").run("2024-01-31");
+        assert_eq!(Ok(("2024-01".to_string(), "-31")), month);
+        // only matches at the current position
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..2,
+                Expected::Label("[0-9]+".to_string()),
+                "x1".to_string()
+            )),
+            regex("[0-9]+").run("x1")
+        );
+
+        let parts = regex_captures::<ParseError>(r"([0-9]{4})-([0-9]{2})(-x)?").run("2024-01;");
+        let groups = vec![
+            Some("2024-01".to_string()),
+            Some("2024".to_string()),
+            Some("01".to_string()),
+            None,
+        ];
+        assert_eq!(Ok((groups, ";")), parts);
+
+        // when streaming, a match is only given once nothing more could change it
+        assert_eq!(
+            Err(ParseError::Incomplete(6, Needed::Unknown)),
+            regex(r"[0-9]{4}-[0-9]{2}").run_streaming("2024-0")
+        );
+        assert_eq!(
+            Ok(("2024-01".to_string(), "-31")),
+            regex::<ParseError>(r"[0-9]{4}-[0-9]{2}").run_streaming("2024-01-31")
+        );
+        assert!(!regex::<ParseError>("[0-9]+")
+            .run_streaming("x1")
+            .unwrap_err()
+            .is_incomplete());
+        assert_eq!(
+            Err(ParseError::Incomplete(7, Needed::Unknown)),
+            regex_captures(r"([0-9]{4})-([0-9]{2})(-x)?").run_streaming("2024-01")
+        );
+    }
+
+    #[cfg(feature = "graphemes")]
+    #[test]
+    fn test_graphemes() {
+        let family = "\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        let graphemes = [
+            "e\u{301}",
+            "\u{1f44d}\u{1f3fd}",
+            family,
+            "\u{1f1eb}\u{1f1f7}",
+            "\u{1f1e9}\u{1f1ea}",
+            "\r\n",
+            "x",
+            // hangul syllables spelled out in jamo, leading, vowel and trailing
+            "\u{1100}\u{1161}\u{11a8}",
+            "\u{1112}\u{1161}",
+            "\u{ac00}\u{11a8}",
+        ];
+        let src = graphemes.concat();
+        let (found, _) = many0(any_grapheme::<ParseError>()).run(&src).unwrap();
+        assert_eq!(graphemes.to_vec(), found);
+
+        assert_eq!(
+            Ok(("e\u{301}a".to_string(), "b")),
+            take_graphemes::<ParseError>(2).run("e\u{301}ab")
+        );
+        assert_eq!(
+            "expected 3 graphemes, found end of input at offset 3",
+            take_graphemes::<ParseError>(3)
+                .run("e\u{301}")
+                .unwrap_err()
+                .to_string()
+        );
+        // the mark might still be on its way
+        let err = take_graphemes::<ParseError>(1)
+            .run_streaming("e")
+            .unwrap_err();
+        assert!(err.is_incomplete());
+        assert_eq!(
+            Ok(("e\u{301}".to_string(), "x")),
+            take_graphemes::<ParseError>(1).run_streaming("e\u{301}x")
+        );
+
+        // regional indicators pair up into flags, however many there are in a row
+        let flags = "\u{1f1ef}\u{1f1f5}\u{1f1f0}\u{1f1f7}\u{1f1fa}";
+        let (found, _) = many0(any_grapheme::<ParseError>()).run(flags).unwrap();
+        assert_eq!(
+            vec!["\u{1f1ef}\u{1f1f5}", "\u{1f1f0}\u{1f1f7}", "\u{1f1fa}"],
+            found
+        );
+    }
+
+    #[cfg(feature = "unicode-ident")]
+    #[test]
+    fn test_unicode_ident() {
+        assert_eq!(Ok(('é', "t")), xid_start::<ParseError>().run("ét"));
+        assert_eq!(Ok(('1', "")), xid_continue::<ParseError>().run("1"));
+        assert!(xid_start::<ParseError>().run("1").is_err());
+
+        assert_eq!(
+            Ok(("_größe2".to_string(), " ")),
+            unicode_ident::<ParseError>().run("_größe2 ")
+        );
+        assert_eq!(
+            Ok(("変数".to_string(), "=")),
+            unicode_ident::<ParseError>().run("変数=")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..4,
+                Expected::Label("identifier".to_string()),
+                "😀".to_string()
+            )),
+            unicode_ident().run("😀")
+        );
+        assert_eq!(
+            Err(ParseError::Incomplete(6, Needed::Unknown)),
+            unicode_ident().run_streaming("変数")
+        );
+    }
+
+    #[test]
+    fn test_keyword() {
+        assert_eq!(
+            Ok(("let".to_string(), " x")),
+            keyword::<ParseError>("let").run("let x")
+        );
+        assert_eq!(
+            Ok(("let".to_string(), "")),
+            keyword::<ParseError>("let").run("let")
+        );
+        assert_eq!(
+            Err(ParseError::Unexpected(3, "t".to_string())),
+            keyword("let").run("letter")
+        );
+    }
+
+    #[test]
+    fn test_lexeme() {
+        let call = pair(
+            lexeme(identifier::<ParseError>()),
+            between(symbol("("), lexeme(digit1()), symbol(")")),
+        );
+        assert_eq!(
+            Ok((("f".to_string(), "1".to_string()), "")),
+            call.run("f ( 1\n) ")
+        );
+
+        let comment = || preceded(p_char::<ParseError>('#'), take_while(|c| c != '\n'));
+        let skip = whitespace1() | comment();
+        let tokens = many0(lexeme_with(digit1(), skip)).run("1 # one\n2");
+        assert_eq!(Ok((vec!["1".to_string(), "2".to_string()], "")), tokens);
+    }
+
+    #[test]
+    fn test_separated_digits() {
+        let rust = || separated_uint::<u64, ParseError>(DigitSeparators::default());
+        assert_eq!(Ok((1000000, "")), rust().run("1_000_000"));
+        assert_eq!(Ok((1000, "u32")), rust().run("10_00u32"));
+        assert_eq!(
+            Err(ParseError::Invalid(0, "1_".to_string())),
+            rust().run("1_")
+        );
+        assert_eq!(
+            Err(ParseError::Invalid(0, "1_".to_string())),
+            rust().run("1__0")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Class("digit".to_string()),
+                "_".to_string()
+            )),
+            rust().run("_1")
+        );
+        // when streaming, another group might follow
+        assert_eq!(
+            Err(ParseError::Incomplete(5, Needed::Unknown)),
+            rust().run_streaming("1_000")
+        );
+        assert_eq!(
+            Err(ParseError::Incomplete(2, Needed::Unknown)),
+            rust().run_streaming("1_")
+        );
+        assert_eq!(Ok((1000, " ")), rust().run_streaming("1_000 "));
+
+        let thousands = || {
+            separated_digits(DigitSeparators {
+                separator: ',',
+                group_size: Some(3),
+            })
+        };
+        assert_eq!(Ok(("12345".to_string(), "")), thousands().run("12,345"));
+        assert_eq!(
+            Err(ParseError::Invalid(0, "1,2345".to_string())),
+            thousands().run("1,2345")
+        );
+        assert_eq!(
+            Err(ParseError::Invalid(0, "1234,567".to_string())),
+            thousands().run("1234,567")
+        );
+    }
+
+    #[test]
+    fn test_offsets() {
+        // offsets count bytes, not chars
+        let err = preceded(p_str("λx".to_string()), digit()).run("λx!");
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                3..4,
+                Expected::Class("digit".to_string()),
+                "!".to_string()
+            )),
+            err
+        );
+        let either = (then(p_char('a'), p_char('b')) | p_char('c')).run("ax");
+        assert_eq!(Some(1), either.err().map(|e: ParseError| e.offset()));
+    }
+
+    #[test]
+    fn test_position() {
+        let src = "one\ntwo\n  λ!";
+        let err = preceded(take_until("!"), digit()).run(src);
+        // λ is two bytes but only one column
+        let pos = Position {
+            offset: 12,
+            line: 3,
+            column: 4,
+        };
+        assert_eq!(Some(pos), err.err().map(|e: ParseError| e.position(src)));
+
+        let start = Position {
+            offset: 4,
+            line: 2,
+            column: 1,
+        };
+        assert_eq!(start, Position::at(src, 4));
+    }
+
+    #[test]
+    fn test_label() {
+        let scheme = || {
+            label(
+                p_str("https".to_string()) | p_str("http".to_string()),
+                "scheme",
+            )
+        };
+        assert_eq!(Ok(("http".to_string(), "://")), scheme().run("http://"));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..1,
+                Expected::Label("scheme".to_string()),
+                "f".to_string()
+            )),
+            scheme().run("ftp://")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Label("scheme".to_string()))),
+            scheme().run("")
+        );
+
+        // an error past the start isn't relabelled
+        let unit = label(pair(p_char('('), p_char(')')), "unit");
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..2,
+                Expected::Literal(")".to_string()),
+                "x".to_string()
+            )),
+            unit.run("(x")
+        );
+    }
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(
+            Some("E0001"),
+            p_char::<ParseError>('a').run("b").unwrap_err().code()
+        );
+        assert_eq!(
+            Some("E0002"),
+            p_char::<ParseError>('a').run("").unwrap_err().code()
+        );
+        let err = (p_char::<ParseError>('a') | p_char('b'))
+            .run("c")
+            .unwrap_err();
+        assert_eq!(Some("E0003"), err.code());
+        let err = cut(p_char::<ParseError>('a')).run("b").unwrap_err();
+        assert_eq!(Some("E0001"), err.code());
+
+        let scheme = || {
+            label_with_code(
+                p_str("https".to_string()) | p_str("http".to_string()),
+                "scheme",
+                "URL0001",
+            )
+        };
+        let err: ParseError = scheme().run("ftp://").unwrap_err();
+        assert_eq!(Some("URL0001"), err.code());
+        assert_eq!("expected scheme, found \"f\" at offset 0", err.to_string());
+        // errors label_with_code doesn't relabel keep their own code
+        let err = preceded(scheme(), p_str("://".to_string()))
+            .run("http:")
+            .unwrap_err();
+        assert_eq!(Some("E0001"), err.code());
+    }
+
+    #[test]
+    fn test_merge() {
+        // nested alternations end up as one flat set, without duplicates
+        let sign = || p_char('+') | p_char('-');
+        let op = (sign() | p_char('*')) | (sign() | p_char('^'));
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Literal("+".to_string()),
+                    Expected::Literal("-".to_string()),
+                    Expected::Literal("*".to_string()),
+                    Expected::Literal("^".to_string())
+                ],
+                Some("/".to_string())
+            )),
+            op.run("/")
+        );
+
+        let end = choice(vec![p_char(';'), eof() ^ ';', newline()]);
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Literal(";".to_string()),
+                    Expected::EndOfInput,
+                    Expected::Literal("\n".to_string())
+                ],
+                Some("x".to_string())
+            )),
+            end.run("x")
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let err = preceded(p_char::<ParseError>('('), digit())
+            .run("(x")
+            .unwrap_err();
+        assert_eq!("expected digit, found \"x\" at offset 1", err.to_string());
+        let err = (verify(digit::<ParseError>(), |c| *c != '0') | p_char('x')).run("0");
+        assert_eq!(
+            "invalid \"0\" at offset 0; expected x, found \"0\" at offset 0",
+            err.unwrap_err().to_string()
+        );
+
+        // works with ?
+        fn parse(s: &str) -> Result<u32, Box<dyn Error>> {
+            Ok(uint::<u32, ParseError>().run(s)?.0)
+        }
+        assert_eq!(12, parse("12").unwrap());
+        assert!(parse("x").is_err());
+    }
+
+    #[test]
+    fn test_custom_error() {
+        // an error that only keeps track of where things went wrong, and whether it's only
+        // that more input is needed
+        #[derive(Debug, PartialEq)]
+        enum At {
+            Wrong(usize),
+            More(usize),
+        }
+
+        impl ParserError for At {
+            fn from_mismatch(span: Range<usize>, _: Expected, _: &str) -> Self {
+                At::Wrong(span.start)
+            }
+
+            fn from_eof(offset: usize, _: Expected) -> Self {
+                At::Wrong(offset)
+            }
+
+            fn merge(errors: Vec<Self>) -> Self {
+                errors
+                    .into_iter()
+                    .max_by_key(|e| e.offset())
+                    .unwrap_or(At::Wrong(0))
+            }
+
+            fn with_context(self, _: &str) -> Self {
+                self
+            }
+
+            fn offset(&self) -> usize {
+                match self {
+                    At::Wrong(at) | At::More(at) => *at,
+                }
+            }
+
+            fn from_incomplete(offset: usize, _: Needed) -> Self {
+                At::More(offset)
+            }
+
+            fn is_incomplete(&self) -> bool {
+                matches!(self, At::More(_))
+            }
+        }
+
+        let digit = || satisfy_named::<_, At>("digit", |c| c.is_ascii_digit());
+        let number = || all_consuming(many1(digit()));
+        assert_eq!(Ok((vec!['1', '2'], "")), number().run("12"));
+        assert_eq!(Err(At::Wrong(2)), number().run("12x"));
+        let sign = satisfy_named("-", |c| c == '-') | satisfy_named("+", |c| c == '+');
+        assert_eq!(Err(At::Wrong(2)), pair(sign, number()).run("-1x"));
+
+        // the built-in primitives work with it too
+        let assign = || {
+            pair(
+                terminated(lexeme(identifier()), symbol("=")),
+                int::<i32, At>(),
+            )
+        };
+        assert_eq!(Ok((("x".to_string(), 1), "")), assign().run("x = 1"));
+        assert_eq!(Err(At::Wrong(4)), assign().run("x = y"));
+
+        // and so do the readers, as it tells them when to read more
+        assert_eq!(Err(At::More(5)), assign().run_streaming("x = 1"));
+        #[cfg(feature = "std")]
+        {
+            let numbers = parse_chunks(lexeme(int::<i32, At>()), ["1 2", "3 4"]);
+            let numbers: Vec<i32> = numbers.map(Result::unwrap).collect();
+            assert_eq!(vec![1, 23, 4], numbers);
+        }
+    }
+
+    #[test]
+    fn test_recover_with() {
+        let stmt = || {
+            terminated(
+                preceded(p_str::<ParseError>("let ".to_string()), identifier()),
+                p_char(';'),
+            )
+        };
+        let program = || many0(terminated(recover_with(stmt(), p_char(';')), multispace0()));
+        let src = "let a; let 1; let b;";
+        let log = DiagnosticLog::new(usize::MAX);
+        let (stmts, rest) = (program().0)(Input::collecting(src, &log)).unwrap();
+        assert_eq!(
+            vec![Some("a".to_string()), None, Some("b".to_string())],
+            stmts
+        );
+        assert_eq!("", rest.rest);
+        assert_eq!(
+            vec![Diagnostic::error(ParseError::Mismatch(
+                11..12,
+                Expected::Label("identifier".to_string()),
+                "1".to_string()
+            ))],
+            log.into_inner()
+        );
+
+        // an alternative that recovered and then failed anyway takes its errors with it
+        let statement =
+            || terminated(recover_with(stmt(), p_char(';')), p_char('!')) | stmt().map(Some);
+        let (_, diagnostics) = statement().run_collecting("let 1;");
+        assert_eq!(1, diagnostics.len());
+        let (parsed, diagnostics) = statement().run_collecting("let a;");
+        assert_eq!(
+            (Some((Some("a".to_string()), "")), vec![]),
+            (parsed, diagnostics)
+        );
+
+        // without anywhere to record errors there's no recovering from them
+        assert_eq!(
+            Ok((vec![Some("a".to_string())], "let 1; let b;")),
+            program().run(src)
+        );
+    }
+
+    #[test]
+    fn test_run_collecting() {
+        let field = || {
+            recover_with(
+                terminated(uint::<u8, ParseError>(), p_char(',')),
+                p_char(','),
+            )
+        };
+        let row = || all_consuming(many0(field()));
+        let (row_value, errors) = row().run_collecting("1,x,300,4,");
+        assert_eq!(Some((vec![Some(1), None, None, Some(4)], "")), row_value);
+        assert_eq!(
+            vec![
+                Diagnostic::error(ParseError::Mismatch(
+                    2..3,
+                    Expected::Class("digit".to_string()),
+                    "x".to_string()
+                )),
+                Diagnostic::error(ParseError::Conversion(
+                    4,
+                    "300".to_string(),
+                    "number too large to fit in target type".to_string()
+                )),
+            ],
+            errors
+        );
+
+        // the error that couldn't be recovered from comes after the ones that could
+        let (row_value, errors) = row().run_collecting("x,5");
+        assert_eq!(None, row_value);
+        assert_eq!(
+            vec![
+                Diagnostic::error(ParseError::Mismatch(
+                    0..1,
+                    Expected::Class("digit".to_string()),
+                    "x".to_string()
+                )),
+                Diagnostic::error(ParseError::Mismatch(
+                    2..3,
+                    Expected::EndOfInput,
+                    "5".to_string()
+                )),
+            ],
+            errors
+        );
+    }
+
+    #[test]
+    fn test_furthest_failure() {
+        // the call got further than the number did, so only its error is reported
+        let call = || pair(identifier(), preceded(p_char('('), p_char(')')));
+        let expr = || map(call(), |(name, _)| name) | digit1();
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                2..3,
+                Expected::Literal(")".to_string()),
+                "x".to_string()
+            )),
+            expr().run("f(x")
+        );
+        // at the same offset everything is still kept
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Label("identifier".to_string()),
+                    Expected::Class("digit".to_string())
+                ],
+                Some("(".to_string())
+            )),
+            expr().run("(")
+        );
+    }
+
+    #[test]
+    fn test_emit_warning() {
+        let item = || lexeme(digit1());
+        let comma = || lexeme(p_char(','));
+        let trailing = || emit_warning(comma(), "trailing comma");
+        let list = || terminated(sep_by(item(), comma()), opt(trailing()));
+        let (items, diagnostics) = list().run_collecting("1, 2,");
+        assert_eq!(Some((vec!["1".to_string(), "2".to_string()], "")), items);
+        assert_eq!(
+            vec![Diagnostic::warning(ParseError::Message(
+                4,
+                "trailing comma".to_string()
+            ))],
+            diagnostics
+        );
+
+        let note = emit(
+            p_str::<ParseError>("var".to_string()),
+            Severity::Note,
+            "use let",
+        );
+        let (_, diagnostics) = note.run_collecting("var");
+        assert_eq!(
+            Some(Severity::Note),
+            diagnostics.first().map(|d| d.severity)
+        );
+        // a plain run just has the value
+        assert_eq!(Ok((vec!["1".to_string()], "")), list().run("1,"));
+
+        // nothing is reported from a branch that was backtracked out of
+        let dead_end = || {
+            opt(pair(
+                emit_warning(p_char('a'), "an a"),
+                fail::<char, _, _>(ParseError::Message(1, "no".to_string())),
+            ))
+        };
+        assert_eq!((Some((None, "a")), vec![]), dead_end().run_collecting("a"));
+        let either = || {
+            pair(emit_warning(p_char::<ParseError>('a'), "an a"), p_char('b'))
+                | pair(p_char('a'), p_char('c'))
+        };
+        assert_eq!(
+            (Some((('a', 'c'), "")), vec![]),
+            either().run_collecting("ac")
+        );
+        let ahead = || {
+            pair(
+                peek(emit_warning(p_char::<ParseError>('a'), "an a")),
+                emit_warning(p_char('a'), "an a"),
+            )
+        };
+        assert_eq!(1, ahead().run_collecting("a").1.len());
+    }
+
+    #[test]
+    fn test_map_err() {
+        let key = || map_err(identifier(), |e: ParseError| e.with_context("key"));
+        assert_eq!(Ok(("a".to_string(), "=1")), key().run("a=1"));
+        assert_eq!(
+            Err(ParseError::Context(
+                "key".to_string(),
+                Box::new(ParseError::Mismatch(
+                    0..1,
+                    Expected::Label("identifier".to_string()),
+                    "1".to_string()
+                ))
+            )),
+            key().run("1=1")
+        );
+
+        // into a type of the caller's own
+        #[derive(Debug, PartialEq)]
+        enum ConfigError {
+            BadPort(usize),
+        }
+        let port = map_err(map_res(digit1(), |s| s.parse::<u16>()), |e: ParseError| {
+            ConfigError::BadPort(e.offset())
+        });
+        assert_eq!(Err(ConfigError::BadPort(0)), port.run("70000"));
+    }
+
+    #[test]
+    fn test_or_else() {
+        // strict dates, then anything date-like while remembering why the strict parse failed
+        let strict = || map_res(terminated(digit1(), eof()), |s| s.parse::<u32>());
+        let date = || {
+            or_else(map(strict(), |d| (d, None)), |e: ParseError| {
+                let why = e.message();
+                map(take_while1(|c: char| c != ' '), move |_| {
+                    (0, Some(why.clone()))
+                })
+            })
+        };
+        assert_eq!(Ok(((20240101, None), "")), date().run("20240101"));
+        assert_eq!(
+            Ok((
+                (
+                    0,
+                    Some("expected end of input, found \"-01-01\"".to_string())
+                ),
+                ""
+            )),
+            date().run("2024-01-01")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(
+                0,
+                Expected::Class("matching character".to_string())
+            )),
+            date().run("")
+        );
+
+        // a cut isn't fallen back from
+        let p = or_else(cut(p_char::<ParseError>('a')), |_| p_char('b'));
+        assert!(p.run("b").unwrap_err().is_cut());
+    }
+
+    #[test]
+    fn test_expected_kinds() {
+        // found, and the span covering it, stop at the end of the line
+        let err = p_str("let".to_string()).run("var x\ny").unwrap_err();
+        let ParseError::Mismatch(span, Expected::Literal(expected), found) = err else {
+            panic!("not a mismatch");
+        };
+        assert_eq!(
+            (0..5, "let", "var x"),
+            (span, expected.as_str(), found.as_str())
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..3,
+                Expected::EndOfInput,
+                " b".to_string()
+            )),
+            all_consuming(p_char('a')).run("a b\nc\nd")
+        );
+
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Class("digit".to_string()))),
+            digit().run("")
+        );
+        assert_eq!(
+            Err(ParseError::EOF(0, Expected::Label("number".to_string()))),
+            label(digit1(), "number").run("")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..2,
+                Expected::EndOfInput,
+                "b".to_string()
+            )),
+            all_consuming(p_char('a')).run("ab")
+        );
+    }
+
+    #[test]
+    fn test_run_collecting_at_most() {
+        let field = || {
+            recover_with(
+                terminated(uint::<u8, ParseError>(), p_char(',')),
+                p_char(','),
+            )
+        };
+        let row = || all_consuming(many0(field()));
+        let (row_value, diagnostics) = row().run_collecting_at_most("1,x,y,z,5,", 2);
+        assert_eq!(None, row_value);
+        assert_eq!(
+            vec![
+                Diagnostic::error(ParseError::Mismatch(
+                    2..3,
+                    Expected::Class("digit".to_string()),
+                    "x".to_string()
+                )),
+                Diagnostic::error(ParseError::Mismatch(
+                    4..5,
+                    Expected::Class("digit".to_string()),
+                    "y".to_string()
+                )),
+                Diagnostic::error(ParseError::Message(
+                    6,
+                    "too many errors, stopping".to_string()
+                )),
+            ],
+            diagnostics
+        );
+
+        // under the limit nothing changes
+        let (row_value, diagnostics) = row().run_collecting_at_most("1,x,5,", 2);
+        assert_eq!(Some((vec![Some(1), None, Some(5)], "")), row_value);
+        assert_eq!(1, diagnostics.len());
+    }
+
+    #[test]
+    fn test_context() {
+        let import = || terminated(identifier(), opt(symbol(",")));
+        let imports = || {
+            context(
+                between(symbol("("), many0(lexeme(import())), symbol(")")),
+                "the import list",
+            )
+        };
+        let header = context(
+            preceded(symbol("module"), pair(lexeme(identifier()), imports())),
+            "module header",
+        );
+        let err = header.run("module m (a, b;)").unwrap_err();
+        assert_eq!(
+            ParseError::Context(
+                "module header".to_string(),
+                Box::new(ParseError::Context(
+                    "the import list".to_string(),
+                    Box::new(ParseError::Mismatch(
+                        14..16,
+                        Expected::Literal(")".to_string()),
+                        ";)".to_string()
+                    ))
+                ))
+            ),
+            err
+        );
+        assert_eq!(
+            "expected ), found \";)\", while parsing the import list, while parsing module header",
+            err.message()
+        );
+
+        // cuts still stop alternation
+        let p = context(cut(p_char::<ParseError>('a')), "a") | p_char('b');
+        assert!(p.run("b").unwrap_err().is_cut());
+    }
+
+    #[test]
+    fn test_bytes() {
+        // a two byte magic number, a big-endian length, then that many little-endian u16s
+        let message = || {
+            preceded(
+                tag(b"\xCA\xFE"),
+                length_count(map(be_u16(), usize::from), le_u16()),
+            )
+        };
+        let src: &[u8] = &[0xCA, 0xFE, 0x00, 0x02, 0x01, 0x00, 0x00, 0x01, 0xFF];
+        assert_eq!(Ok((vec![1, 256], &[0xFF][..])), message().run(src));
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                0..2,
+                Expected::Literal("[202, 254]".to_string()),
+                "[202, 255]".to_string()
+            )),
+            message().run(&[0xCA, 0xFF, 0x00])
+        );
+        assert_eq!(
+            Err(ParseError::Repetition(
+                1,
+                Box::new(ParseError::EOF(6, Expected::Label("2 bytes".to_string())))
+            )),
+            message().run(&[0xCA, 0xFE, 0x00, 0x02, 0x01, 0x00])
+        );
+
+        // the generic combinators work the same over bytes
+        let ascii_digits =
+            || recognize(many1(satisfy_byte::<_, ParseError>(|b| b.is_ascii_digit())));
+        let fields = sep_by(ascii_digits(), byte(b','));
+        assert_eq!(
+            Ok((vec![b"12".to_vec(), b"3".to_vec()], &b";"[..])),
+            fields.run(b"12,3;")
+        );
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..2,
+                Expected::EndOfInput,
+                "10".to_string()
+            )),
+            all_consuming(byte(b'x') | byte(b'y')).run(b"y\n")
+        );
+        assert_eq!(
+            Ok((0xDEADBEEF, &[][..])),
+            be_u32::<ParseError>().run(&[0xDE, 0xAD, 0xBE, 0xEF])
+        );
+        assert_eq!(
+            Ok((0x04030201, &[][..])),
+            le_u32::<ParseError>().run(&[1, 2, 3, 4])
+        );
+    }
+
+    #[test]
+    fn test_tokens() {
+        #[derive(Debug, PartialEq, Clone)]
+        enum Tok {
+            LParen,
+            RParen,
+            Plus,
+            Num(i64),
+        }
+        // lex first...
+        let lex = || {
+            many0(lexeme(
+                (p_char('(') ^ Tok::LParen)
+                    | (p_char(')') ^ Tok::RParen)
+                    | (p_char('+') ^ Tok::Plus)
+                    | map(int::<i64, ParseError>(), Tok::Num),
+            ))
+        };
+        // ...then parse the tokens
+        fn sum() -> Parser<i64, ParseError, [Tok]> {
+            let num = || {
+                map(token_if(|t| matches!(t, Tok::Num(_))), |t| match t {
+                    Tok::Num(n) => n,
+                    _ => unreachable!(),
+                })
+            };
+            let atom = || num() | between(token(Tok::LParen), lazy(sum), token(Tok::RParen));
+            map(sep_by1(atom(), token(Tok::Plus)), |ns| ns.iter().sum())
+        }
+        let (tokens, _) = lex().run("1 + (2 + 3)").unwrap();
+        assert_eq!(Ok((6, &[][..])), all_consuming(sum()).run(&tokens));
+
+        let (tokens, _) = lex().run("(2 3)").unwrap();
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                2..3,
+                Expected::Literal("RParen".to_string()),
+                "Num(3)".to_string()
+            )),
+            sum().run(&tokens)
+        );
+        let (tokens, _) = lex().run("1 +").unwrap();
+        assert_eq!(
+            Err(ParseError::Mismatch(
+                1..2,
+                Expected::EndOfInput,
+                "Plus".to_string()
+            )),
+            all_consuming(sum()).run(&tokens)
+        );
+    }
+
+    #[test]
+    fn test_streaming() {
+        // a big-endian length followed by that many bytes
+        let frame = || length_count(map(be_u16(), usize::from), satisfy_byte(|_| true));
+        assert_eq!(
+            Ok((vec![7, 8], &[9][..])),
+            frame().run_streaming(&[0, 2, 7, 8, 9])
+        );
+        assert_eq!(
+            Err(ParseError::Incomplete(1, Needed::Size(1))),
+            frame().run_streaming(&[0])
+        );
+        assert_eq!(
+            Err(ParseError::Incomplete(3, Needed::Size(1))),
+            frame().run_streaming(&[0, 2, 7])
+        );
+        // the same input is just wrong when it's all there is
+        assert_eq!(
+            Err(ParseError::Repetition(
+                1,
+                Box::new(ParseError::EOF(
+                    3,
+                    Expected::Class("matching byte".to_string())
+                ))
+            )),
+            frame().run(&[0, 2, 7])
+        );
+
+        // alternatives aren't tried while the input might still turn out to match
+        let method = || p_str("GET".to_string()) | p_str("POST".to_string());
+        assert_eq!(
+            Err(ParseError::Incomplete(2, Needed::Size(1))),
+            method().run_streaming("GE")
+        );
+        assert_eq!(
+            Err(ParseError::Expected(
+                0,
+                vec![
+                    Expected::Literal("GET".to_string()),
+                    Expected::Literal("POST".to_string())
+                ],
+                Some("PUT".to_string())
+            )),
+            method().run_streaming("PUT")
+        );
+        let line = || terminated(many0(satisfy(|c| c != '\n')), newline::<ParseError>());
+        assert!(line().run_streaming("abc").unwrap_err().is_incomplete());
+        assert_eq!(3, line().run_streaming("abc\nd").unwrap().0.len());
+        assert_eq!(
+            Err(ParseError::Incomplete(3, Needed::Unknown)),
+            digit1().run_streaming("123")
+        );
+        assert_eq!(
+            Ok(("123".to_string(), "")),
+            digit1::<ParseError>().run("123")
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_parse_reader() {
+        // hands out a few bytes at a time, splitting chars and lines between reads
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let n = self.0.len().min(buf.len()).min(3);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Ok(n)
+            }
+        }
+        let line = || terminated(take_while(|c| c != '\n'), newline::<ParseError>());
+        let lines: Vec<_> = parse_reader(line(), Trickle("héllo\nwörld\n".as_bytes())).collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("héllo", lines[0].as_ref().unwrap());
+        assert_eq!("wörld", lines[1].as_ref().unwrap());
+
+        // the last item can end with the input
+        let word = || lexeme(alpha1::<ParseError>());
+        let words: Result<Vec<_>, _> = parse_reader(word(), Trickle(b"ab cd  ef")).collect();
+        assert_eq!(vec!["ab", "cd", "ef"], words.unwrap());
+
+        // offsets count from the start of everything that was read
+        let mut numbers = parse_reader(lexeme(digit1::<ParseError>()), Trickle(b"12 345 x"));
+        assert_eq!("12", numbers.next().unwrap().unwrap());
+        assert_eq!("345", numbers.next().unwrap().unwrap());
+        match numbers.next() {
+            Some(Err(ReadError::Parse(e))) => assert_eq!(7, e.offset()),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(numbers.next().is_none());
+
+        let frames: Vec<_> = parse_reader(be_u16::<ParseError>(), Trickle(&[0, 1, 0, 2, 0]))
+            .map(|r| r.map_err(|e| e.to_string()))
+            .collect();
+        assert_eq!(
+            vec![
+                Ok(1),
+                Ok(2),
+                Err("expected 2 bytes, found end of input at offset 5".to_string())
+            ],
+            frames
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let src = "let  x = yz";
+        let words = many0(recognize_span(lexeme(alpha1::<ParseError>())));
+        let (spans, _) = words.run(src).unwrap();
+        let words: Vec<&str> = spans.iter().map(|span| span.of(src).trim_end()).collect();
+        assert_eq!(vec!["let", "x"], words);
+        assert_eq!(Span { start: 0, end: 5 }, spans[0]);
+        // nothing is copied at all when the words come from a _span primitive or the parse
+        // module, as lexeme doesn't keep the whitespace it skips
+        let word = || lexeme(take_while1_span::<_, ParseError>(char::is_alphabetic));
+        let (spans, _) = many0(word()).run(src).unwrap();
+        let words: Vec<&str> = spans.iter().map(|span| span.of(src)).collect();
+        assert_eq!(vec!["let", "x"], words);
+        use crate::parse::Parse;
+        let word = || lexeme(parse::take_while1::<_, ParseError>(char::is_alphabetic).boxed());
+        let (spans, _) = many0(word()).run(src).unwrap();
+        let words: Vec<&str> = spans.iter().map(|span| span.of(src)).collect();
+        assert_eq!(vec!["let", "x"], words);
+
+        // the _span primitives match just like the ones that copy
+        let src = "let x = y;";
+        let (span, rest) = p_str_span::<ParseError>("let").run(src).unwrap();
+        assert_eq!(("let", " x = y;"), (span.of(src), rest));
+        let (span, _) = take_until_span::<ParseError>("=").run(src).unwrap();
+        assert_eq!("let x ", span.of(src));
+        let (span, _) = take_span::<ParseError>(5).run(src).unwrap();
+        assert_eq!("let x", span.of(src));
+        let (span, _) = take_while_span::<_, ParseError>(|c| c != ';')
+            .run(src)
+            .unwrap();
+        assert_eq!("let x = y", span.of(src));
+        assert_eq!(
+            p_str::<ParseError>("var".to_string()).run(src).unwrap_err(),
+            p_str_span("var").run(src).unwrap_err()
+        );
+
+        let (((), span), _) = spanned(pure::<_, ParseError, _>(())).run("a").unwrap();
+        assert_eq!(0, span.len());
+
+        let src: &[u8] = &[1, 2, 3, 4];
+        let (span, _) = preceded(byte::<ParseError>(1), recognize_span(take_bytes(2)))
+            .run(src)
+            .unwrap();
+        assert_eq!(&[2, 3], span.of(src));
+    }
+
+    #[test]
+    fn test_state() {
+        // a name is only a type once it's been declared as one, as with typedefs in C
+        let typedef = || {
+            let name = preceded(
+                symbol("type"),
+                terminated(lexeme(identifier()), symbol(";")),
+            );
+            bind(name, |name| {
+                update_state(move |types: &mut Vec<String>| types.push(name.clone()))
+            })
+        };
+        let type_name = || {
+            bind(lexeme(identifier()), |name| {
+                bind(get_state(), move |types: Vec<String>| {
+                    if types.contains(&name) {
+                        pure(name.clone())
+                    } else {
+                        fail(ParseError::Message(0, format!("{} isn't a type", name)))
+                    }
+                })
+            })
+        };
+        let var = || terminated(pair(type_name(), lexeme(identifier())), symbol(";"));
+        let program = || preceded(many0(typedef()), many1(var()));
+
+        let (result, types) =
+            program().run_with_state("type id; id x; id y;", Vec::<String>::new());
+        assert_eq!(2, result.unwrap().0.len());
+        assert_eq!(vec!["id".to_string()], types);
+        let (result, _) = program().run_with_state("type id; num x;", Vec::<String>::new());
+        assert_eq!("num isn't a type", result.unwrap_err().message());
+
+        let count = || {
+            many0(terminated(
+                p_char::<ParseError>('a'),
+                update_state(|n: &mut usize| *n += 1),
+            ))
+        };
+        let (_, n) = preceded(set_state(10usize), count()).run_with_state("aaab", 0usize);
+        assert_eq!(13, n);
+    }
+
+    #[test]
+    fn test_located() {
+        let word = || located(lexeme(alpha1::<ParseError>()));
+        let words = || preceded(multispace0(), many1(word()));
+        let (words, _) = words().run_named("words.txt", "one\n  two").unwrap();
+        assert_eq!(
+            Located {
+                value: "two".to_string(),
+                span: Span { start: 6, end: 9 },
+                start: Position {
+                    offset: 6,
+                    line: 2,
+                    column: 3
+                },
+                name: Some("words.txt".to_string()),
+            },
+            words[1]
+        );
+        let (word, _) = word().run("x").unwrap();
+        assert_eq!(None, word.name);
+
+        // inside map_parser, lines count from the start of the region
+        let line = terminated(take_while(|c| c != '\n'), newline::<ParseError>());
+        let second = preceded(
+            line,
+            map_parser(
+                take_while(|c| c != '!'),
+                preceded(p_char('b'), located(alpha1())),
+            ),
+        );
+        let (word, _) = second.run("a\nbc!").unwrap();
+        assert_eq!(
+            (3, 1, 2),
+            (word.start.offset, word.start.line, word.start.column)
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_parse_async_reader() {
+        use futures_executor::block_on;
+
+        // hands out a byte at a time, with nothing ready every other time it's asked
+        struct Trickle<'a>(&'a [u8], bool);
+        impl AsyncRead for Trickle<'_> {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut [u8],
+            ) -> Poll<io::Result<usize>> {
+                self.1 = !self.1;
+                if self.1 {
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                let n = self.0.len().min(buf.len()).min(1);
+                buf[..n].copy_from_slice(&self.0[..n]);
+                self.0 = &self.0[n..];
+                Poll::Ready(Ok(n))
+            }
+        }
+        let mut words = parse_async_reader(
+            lexeme(take_while1::<_, ParseError>(char::is_alphabetic)),
+            Trickle("añb cd 1".as_bytes(), false),
+        );
+        let mut found = Vec::new();
+        while let Some(word) = block_on(words.next_async()) {
+            found.push(word.map_err(|e| e.to_string()));
+        }
+        assert_eq!(
+            vec![
+                Ok("añb".to_string()),
+                Ok("cd".to_string()),
+                Err("expected matching character, found \"1\" at offset 8".to_string())
+            ],
+            found
+        );
+
+        // any reader of futures' will do
+        let reader = futures_util::io::Cursor::new(b"12 34".to_vec());
+        let mut numbers = parse_async_reader(lexeme(digit1::<ParseError>()), reader);
+        let mut found = Vec::new();
+        while let Some(n) = block_on(numbers.next_async()) {
+            found.push(n.unwrap());
+        }
+        assert_eq!(vec!["12".to_string(), "34".to_string()], found);
+
+        // the chunks of a stream split words, and even chars, wherever they like
+        let text = "añb cd e".as_bytes();
+        let chunks: Vec<io::Result<Vec<u8>>> = vec![
+            Ok(text[..2].to_vec()),
+            Ok(text[2..6].to_vec()),
+            Ok(text[6..].to_vec()),
+            Err(io::Error::other("connection reset")),
+        ];
+        let mut words = parse_stream(
+            lexeme(take_while1::<_, ParseError>(char::is_alphabetic)),
+            futures_util::stream::iter(chunks),
+        );
+        let mut found = Vec::new();
+        while let Some(word) = block_on(words.next_async()) {
+            found.push(word.map_err(|e| e.to_string()));
+        }
+        assert_eq!(
+            vec![
+                Ok("añb".to_string()),
+                Ok("cd".to_string()),
+                Err("connection reset".to_string())
+            ],
+            found
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_run_file() {
+        let path = std::env::temp_dir().join(format!("rparsec-{}.txt", std::process::id()));
+        std::fs::write(&path, "one twö\nthree").unwrap();
+        let words = || {
+            sep_by(
+                recognize_span(take_while1(char::is_alphanumeric)),
+                multispace1::<ParseError>(),
+            )
+        };
+        // only this test uses the file, and it's left alone while it's mapped
+        type Mapped<A> = Result<(A, MappedFile), ReadError<ParseError>>;
+        fn run<A>(p: Parser<A, ParseError>, path: &Path) -> Mapped<A> {
+            unsafe { p.run_file(path) }
+        }
+        let (spans, file) = run(words(), &path).unwrap();
+        let src = std::str::from_utf8(file.as_bytes()).unwrap();
+        let found: Vec<&str> = spans.iter().map(|span| span.of(src)).collect();
+        assert_eq!(vec!["one", "twö", "three"], found);
+        drop(file);
+
+        // all of it has to be parsed
+        let err = run(many0(alpha1()), &path).map(|_| ()).unwrap_err();
+        assert_eq!(
+            "expected end of input, found \" twö\" at offset 3",
+            err.to_string()
+        );
+
+        std::fs::write(&path, [0xff]).unwrap();
+        assert!(matches!(run(words(), &path), Err(ReadError::Io(_))));
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(0, run(words(), &path).unwrap().0.len());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(run(words(), &path), Err(ReadError::Io(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_parse_chunks() {
+        // statements split across chunks, with a char split between two of them
+        let chunks: Vec<&[u8]> = vec![b"let a", b" = 1;\nlet \xc3", b"\xa9 = 22;", b"", b"\n"];
+        let stmt = || {
+            let name = preceded(
+                symbol::<ParseError>("let"),
+                lexeme(take_while1(char::is_alphabetic)),
+            );
+            let value = terminated(lexeme(digit1()), symbol(";"));
+            pair(terminated(name, symbol("=")), value)
+        };
+        let stmts: Result<Vec<_>, _> = parse_chunks(lexeme(stmt()), chunks).collect();
+        assert_eq!(
+            vec![
+                ("a".to_string(), "1".to_string()),
+                ("é".to_string(), "22".to_string())
+            ],
+            stmts.unwrap()
+        );
+
+        // tokens split where one chunk ends and the next begins
+        let line = || terminated(take_while1(|c| c != '\r'), line_ending::<ParseError>());
+        let lines: Result<Vec<_>, _> = parse_chunks(line(), ["ab\r", "\ncd\r\n"]).collect();
+        assert_eq!(vec!["ab", "cd"], lines.unwrap());
+        let floats = parse_chunks(lexeme(float::<f64, ParseError>()), ["1.5 2.", "75"]);
+        let floats: Result<Vec<_>, _> = floats.collect();
+        assert_eq!(vec![1.5, 2.75], floats.unwrap());
+        let names = parse_chunks(lexeme(identifier::<ParseError>()), ["ab cd", "ef"]);
+        let names: Result<Vec<_>, _> = names.collect();
+        assert_eq!(vec!["ab", "cdef"], names.unwrap());
+
+        let none: Vec<String> = Vec::new();
+        assert!(parse_chunks(digit1::<ParseError>(), none).next().is_none());
+    }
+
+    #[test]
+    fn test_methods() {
+        // key = value, value, ...
+        let key = || lexeme(identifier::<ParseError>()).label("key");
+        let value = || lexeme(digit1()).map(|d| d.parse::<u32>().unwrap());
+        let values = || value().sep_by(symbol(",")).verify(|vs| !vs.is_empty());
+        let entry = || {
+            key()
+                .skip(symbol("="))
+                .pair(values().cut())
+                .context("entry")
+        };
+        assert_eq!(
+            Ok((("a".to_string(), vec![1, 23]), "")),
+            entry().run("a = 1, 23")
+        );
+        assert_eq!(
+            "expected key, found \"=\" at offset 0, while parsing entry",
+            entry().run("= 1").unwrap_err().to_string()
+        );
+
+        let sign = || p_char::<ParseError>('-').or(p_char('+')).opt();
+        assert_eq!(Ok((None, "1")), sign().run("1"));
+        let digits = || digit().many1().recognize();
+        let number = sign().then(digits()).map(|d| d.len());
+        assert_eq!(Ok((3, "")), number.run("+123"));
+        assert_eq!(
+            Ok((vec!['a', 'a'], "b")),
+            p_char::<ParseError>('a').many().run("aab")
+        );
+    }
+
+    #[test]
+    fn test_clone() {
+        // one parser for the digits on both sides of the point
+        let digits = digit1::<ParseError>();
+        let decimal = pair(digits.clone(), preceded(p_char('.'), digits.clone()));
+        let number = p_or(decimal, map(digits, |d| (d, String::new())));
+        assert_eq!(
+            Ok((("12".to_string(), "5".to_string()), "")),
+            number.run("12.5")
+        );
+        assert_eq!(Ok((("7".to_string(), String::new()), "")), number.run("7"));
+    }
+
+    #[test]
+    fn test_parse() {
+        use crate::parse::{self, Parse};
+
+        let number = || {
+            parse::take_while1::<_, ParseError>(|c| c.is_ascii_digit())
+                .skip(parse::char(' ').many())
+        };
+        let list = || {
+            parse::char('[')
+                .then(number().many())
+                .skip(parse::char(']'))
+        };
+        let src = "[1 23 456]";
+        let (spans, rest) = list().run(src).unwrap();
+        let numbers: Vec<&str> = spans.iter().map(|span| span.of(src)).collect();
+        assert_eq!((vec!["1", "23", "456"], ""), (numbers, rest));
+
+        // the errors are the same as the boxed combinators give
+        let scheme = parse::literal("https").or(parse::literal("http"));
+        let boxed_scheme = p_str::<ParseError>("https".to_string()) | p_str("http".to_string());
+        assert_eq!(Ok(("http", "://")), scheme.run("http://"));
+        assert_eq!(
+            boxed_scheme.run("ftp://").unwrap_err(),
+            scheme.run("ftp://").unwrap_err()
+        );
+        assert_eq!(
+            number().run("x").unwrap_err(),
+            take_while1(|c| c.is_ascii_digit()).run("x").unwrap_err()
+        );
+
+        // boxed parsers work in here, and these can be boxed
+        let sign = parse::char::<ParseError>('-').or(p_char('+')).opt();
+        assert_eq!(Ok((Some('+'), "1")), sign.run("+1"));
+        let list = map(list().boxed(), |spans| spans.len());
+        assert_eq!(Ok((3, "")), list.run(src));
+
+        // p matching nothing still has to match at least once
+        let blanks = || {
+            parse::take_while::<_, ParseError>(|c| c == ' ')
+                .many1()
+                .map(|spans| spans.len())
+        };
+        assert_eq!(Ok((1, "x")), blanks().run("x"));
+        assert_eq!(
+            Ok((1, "x")),
+            map(many1(take_while::<_, ParseError>(|c| c == ' ')), |s| s
+                .len())
+            .run("x")
+        );
+
+        let streaming = parse::take_while::<_, ParseError>(|c| c.is_ascii_digit()).recognize();
+        assert!(streaming
+            .boxed()
+            .run_streaming("12")
+            .unwrap_err()
+            .is_incomplete());
+    }
+
+    #[test]
+    fn test_boxed() {
+        use crate::parse::{self, BoxedParser, Parse};
+
+        // parsers of different types, made the same type so they can be kept together
+        let value = |s: &str| s.len();
+        let values: Vec<BoxedParser<usize, ParseError>> = vec![
+            parse::literal("true").map(value).boxed(),
+            parse::take_while1(|c| c.is_ascii_digit())
+                .map(|span| span.len())
+                .boxed(),
+            map(quoted_string(QuoteConfig::default()), |s| s.len()).boxed(),
+        ];
+        let value = choice(values);
+        assert_eq!(Ok((4, "")), value.run("true"));
+        assert_eq!(Ok((3, "")), value.run("123"));
+        assert_eq!(Ok((2, "")), value.run("\"ab\""));
+        assert!(value.run("x").is_err());
+    }
+
+    #[test]
+    fn test_shared() {
+        use crate::parse::{self, Parse, SyncParser};
+        use std::sync::OnceLock;
+
+        static WORDS: OnceLock<SyncParser<Vec<Span>, ParseError>> = OnceLock::new();
+        let words = || {
+            WORDS.get_or_init(|| {
+                let word = parse::take_while1(|c| c.is_alphabetic());
+                word.skip(parse::char(' ').many()).many().shared()
+            })
+        };
+        let counts: Vec<usize> = std::thread::scope(|scope| {
+            let threads: Vec<_> = ["a bc", "d e f", ""]
+                .into_iter()
+                .map(|src| scope.spawn(move || words().run(src).unwrap().0.len()))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert_eq!(vec![2, 3, 0], counts);
+
+        // a shared parser is a part like any other, and what it's part of can be shared too
+        let list = parse::char('[')
+            .then(words().clone())
+            .skip(parse::char(']'))
+            .shared();
+        let count = std::thread::scope(|scope| {
+            let (words, _) = scope.spawn(|| list.run("[a b]")).join().unwrap().unwrap();
+            words.len()
+        });
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn test_operators() {
+        // assignment ::= "let" ident "=" digits ";"
+        let ident = || lexeme(identifier::<ParseError>());
+        let assignment =
+            symbol("let") >> (ident() + (symbol("=") >> lexeme(digit1()))) << p_char(';');
+        assert_eq!(
+            Ok((("x".to_string(), "12".to_string()), "")),
+            assignment.run("let x = 12;")
+        );
+
+        // keywords aren't names
+        let keyword = || symbol("let") | symbol("in");
+        let name = !keyword() >> ident() | p_char('_') ^ "_".to_string();
+        assert_eq!(Ok(("x".to_string(), "")), name.run("x"));
+        assert_eq!(Ok(("_".to_string(), "")), name.run("_"));
+        let err = name.run("in").unwrap_err();
+        assert_eq!(0, err.offset());
+    }
+
+    #[test]
+    fn test_into_parser() {
+        let expr = || digit1::<ParseError>();
+        let parens = '('.then(expr()).skip(')');
+        assert_eq!(Ok(("12".to_string(), "")), parens.run("(12)"));
+
+        let binding = ("let " >> identifier() << " = ") + expr();
+        assert_eq!(
+            Ok((("x".to_string(), "1".to_string()), "")),
+            binding.run("let x = 1")
+        );
+        let sign = '+' | p_char::<ParseError>('-');
+        assert_eq!(Ok(('-', "1")), sign.run("-1"));
+        assert_eq!(
+            Ok::<_, ParseError>(("b".to_string(), "")),
+            "a".or("b").run("b")
+        );
+
+        // a parser written by hand
+        fn two(inp: Input) -> Result<(usize, Input), ParseError> {
+            match inp.rest.get(..2) {
+                Some(s) => Ok((s.len(), inp.advance(2))),
+                None => Err(ParseError::EOF(
+                    inp.offset,
+                    Expected::Label("two".to_string()),
+                )),
+            }
+        }
+        assert_eq!(
+            Ok(('x', 2)),
+            p_char('x').pair(two).run("xab").map(|(r, _)| r)
+        );
+        assert_eq!(
+            Ok((vec!['1', '2'], "")),
+            digit::<ParseError>().sep_by(',').run("1,2")
+        );
+
+        // the free combinators take them too
+        assert_eq!(
+            Ok(("12".to_string(), "")),
+            between('(', expr(), ')').run("(12)")
+        );
+        assert_eq!(
+            Ok(('x', 2)),
+            preceded("let ", pair('x', two))
+                .run("let xab")
+                .map(|(r, _)| r)
+        );
+        // a closure has to be wrapped up first
+        let first = Parser(Rc::new(|inp: Input| match inp.rest.chars().next() {
+            Some(c) => Ok((c, inp.advance(c.len_utf8()))),
+            None => Err(ParseError::EOF(
+                inp.offset,
+                Expected::Label("any".to_string()),
+            )),
+        }));
+        assert_eq!(Ok(('b', "")), preceded('a', first).run("ab"));
+    }
+
+    #[test]
+    fn test_prelude() {
+        mod grammar {
+            use crate::prelude::*;
+
+            pub fn list() -> Parser<Vec<String>, ParseError> {
+                '['.then(sep_by(digit1(), symbol(",")))
+                    .skip(']')
+                    .label("list")
+            }
+        }
+        assert_eq!(
+            Ok((vec!["1".to_string(), "2".to_string()], "")),
+            grammar::list().run("[1, 2]")
+        );
+    }
+
+    #[test]
+    fn test_parse_entry_points() {
+        let number = || lexeme(digit1::<ParseError>());
+        assert_eq!(Ok("12".to_string()), number().parse("12 "));
+        let err = number().parse("12 3").unwrap_err();
+        assert_eq!(3, err.offset());
+        assert_eq!(Ok(("12".to_string(), "3")), number().parse_partial("12 3"));
+
+        let src = "a = 1\nb = x";
+        assert_eq!(Ok(("1".to_string(), "b = x")), number().parse_at(src, 4));
+        let err = number().parse_at(src, 10).unwrap_err();
+        assert_eq!(
+            Position {
+                offset: 10,
+                line: 2,
+                column: 5
+            },
+            err.position(src)
+        );
+
+        // an offset parsing can't start at is an error rather than a panic
+        let err = number().parse_at(src, 12).unwrap_err();
+        assert_eq!(
+            "can't start parsing outside the input or inside a char at offset 12",
+            err.to_string()
+        );
+        assert_eq!(Some("E0009"), err.code());
+        assert!(number().parse_at(src, src.len()).is_err());
+        assert!(number().parse_at("é1", 1).is_err());
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // nested ::= '(' nested? ')'
+        let nested =
+            || recursive(|nested| p_char('(').then(opt(nested)).skip(p_char(')')).map(|_| ()));
+        let deep = |n: usize| "(".repeat(n) + &")".repeat(n);
+
+        // the innermost ( tries nested once more, to find it isn't there
+        assert_eq!(Ok(()), nested().parse(&deep(DEFAULT_MAX_DEPTH - 1)));
+        let err = nested().parse(&deep(100_000)).unwrap_err();
+        assert_eq!(
+            ParseError::Cut(Box::new(ParseError::TooDeep(DEFAULT_MAX_DEPTH))),
+            err
+        );
+        assert_eq!(Some("E0008"), err.code());
+
+        let limited = || depth_limit(nested(), 3);
+        assert_eq!(Ok(()), limited().parse("(())"));
+        assert_eq!(
+            "input nested too deeply",
+            limited().parse("((()))").unwrap_err().message()
+        );
+    }
+
+    #[cfg(feature = "memoize")]
+    #[test]
+    fn test_memoize() {
+        use std::cell::Cell;
+
+        // every alternative starts with a term, which is only parsed once at each offset
+        let runs = Rc::new(Cell::new(0));
+        let counted = runs.clone();
+        let term = memoize(digit1::<ParseError>().map(move |n| {
+            counted.set(counted.get() + 1);
+            n
+        }));
+        let expr = ((term.clone() << '+') + term.clone())
+            | ((term.clone() << '-') + term.clone())
+            | term.clone().map(|n| (n, "0".to_string()));
+        assert_eq!(Ok(("12".to_string(), "0".to_string())), expr.parse("12"));
+        assert_eq!(1, runs.get());
+
+        // another run starts afresh
+        assert_eq!(Ok(("3".to_string(), "4".to_string())), expr.parse("3-4"));
+        assert_eq!(3, runs.get());
+
+        // each result of parse_iter is a run of its own, so the term looked ahead at is
+        // forgotten once the one before it has been parsed, and parsed again
+        let item = terminated(term.clone(), opt(peek(preceded(',', term.clone())))) << opt(',');
+        let items: Result<Vec<_>, _> = parse_iter(item, "5,6").collect();
+        assert_eq!(Ok(vec!["5".to_string(), "6".to_string()]), items);
+        assert_eq!(6, runs.get());
+
+        // map_parser's region starts at the same offset but ends sooner, so it's parsed anew
+        let digits = memoize(digit1::<ParseError>());
+        let both = peek(digits.clone()) + map_parser(take(2), digits);
+        assert_eq!(
+            Ok((("1234".to_string(), "12".to_string()), "34")),
+            both.run("1234")
+        );
+
+        // what was recorded is recorded again when the alternative it was rewound with is
+        // looked up
+        let field = memoize(recover_with(
+            terminated(uint::<u8, ParseError>(), p_char(',')),
+            p_char(','),
+        ));
+        let row = (field.clone() << p_char('a')) | field;
+        let (parsed, diagnostics) = row.run_collecting("x,");
+        assert_eq!(Some((None, "")), parsed);
+        assert_eq!(
+            vec![Diagnostic::error(ParseError::Mismatch(
+                0..1,
+                Expected::Class("digit".to_string()),
+                "x".to_string()
+            ))],
+            diagnostics
+        );
+    }
 }
@@ -0,0 +1,442 @@
+// parsers without the boxes. each combinator here is a struct generic over the parsers it's
+// made of, so a grammar built out of them is one concrete type that the compiler can inline
+// all the way through, with nothing allocated to build it and no dynamic calls to run it.
+// `boxed` turns one into a Parser wherever the type needs erasing, and a Parser can be used
+// in here as it is. text these match comes back as a Span rather than copied out
+
+use alloc::rc::Rc;
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::combinator::Span;
+use crate::error::*;
+use crate::primitive::{mismatch, prefix_len, unexpected};
+use crate::{Input, Parser, Source};
+
+pub trait Parse<S: Source + ?Sized = str> {
+    type Output;
+    type Error;
+
+    // parses the start of inp, like what's inside a Parser. it isn't called parse so that
+    // it can't be mistaken for Parser::parse, which is for all of a &S
+    fn parse_input<'a>(
+        &self,
+        inp: Input<'a, S>,
+    ) -> Result<(Self::Output, Input<'a, S>), Self::Error>;
+
+    // like Parser::run
+    fn run<'a>(&self, inp: &'a S) -> Result<(Self::Output, &'a S), Self::Error> {
+        let (r, rest) = self.parse_input(Input::new(inp))?;
+        Ok((r, rest.rest))
+    }
+
+    fn map<B, F: Fn(Self::Output) -> B>(self, f: F) -> Map<Self, F>
+    where
+        Self: Sized,
+    {
+        Map(self, f)
+    }
+
+    // keeps the result of next, like then
+    fn then<Q: Parse<S, Error = Self::Error>>(self, next: Q) -> Then<Self, Q>
+    where
+        Self: Sized,
+    {
+        Then(self, next)
+    }
+
+    // keeps this result, like terminated
+    fn skip<Q: Parse<S, Error = Self::Error>>(self, next: Q) -> Skip<Self, Q>
+    where
+        Self: Sized,
+    {
+        Skip(self, next)
+    }
+
+    fn pair<Q: Parse<S, Error = Self::Error>>(self, next: Q) -> Pair<Self, Q>
+    where
+        Self: Sized,
+    {
+        Pair(self, next)
+    }
+
+    fn or<Q: Parse<S, Output = Self::Output, Error = Self::Error>>(self, other: Q) -> Or<Self, Q>
+    where
+        Self: Sized,
+    {
+        Or(self, other)
+    }
+
+    fn opt(self) -> Opt<Self>
+    where
+        Self: Sized,
+    {
+        Opt(self)
+    }
+
+    // like many0
+    fn many(self) -> Many<Self>
+    where
+        Self: Sized,
+    {
+        Many { p: self, min: 0 }
+    }
+
+    fn many1(self) -> Many<Self>
+    where
+        Self: Sized,
+    {
+        Many { p: self, min: 1 }
+    }
+
+    fn recognize(self) -> Recognize<Self>
+    where
+        Self: Sized,
+    {
+        Recognize(self)
+    }
+
+    fn boxed(self) -> Parser<Self::Output, Self::Error, S>
+    where
+        Self: Sized + 'static,
+    {
+        Parser(Rc::new(move |inp: Input<S>| self.parse_input(inp)))
+    }
+
+    // like boxed, but what it gives can be shared between threads
+    fn shared(self) -> SyncParser<Self::Output, Self::Error, S>
+    where
+        Self: Sized + Send + Sync + 'static,
+    {
+        SyncParser(Arc::new(move |inp: Input<S>| self.parse_input(inp)))
+    }
+}
+
+// what boxed gives: any parser at all with this output and error, whatever it was built
+// from. these can go in collections, and keep the types of big grammars from getting huge
+pub type BoxedParser<A, E, S = str> = Parser<A, E, S>;
+
+impl<A, E, S: Source + ?Sized> Parser<A, E, S> {
+    // already boxed, so it's given back as it is rather than boxed again
+    pub fn boxed(self) -> BoxedParser<A, E, S> {
+        self
+    }
+}
+
+// what a SyncParser runs
+pub type SyncParseFn<A, E, S = str> = dyn Fn(Input<S>) -> Result<(A, Input<S>), E> + Send + Sync;
+
+// what shared gives: like BoxedParser, but Send and Sync, so it can be used from many
+// threads at once or kept in a static. Parsers can't be, as the combinators that build
+// them share their parts with Rc. so a grammar to be shared has to be made of the parsers
+// in here, and only these combinators take a SyncParser as one of its parts. a Parser
+// anywhere inside, including one from combinator or primitive, stops it being shared
+pub struct SyncParser<A, E, S: ?Sized = str>(Arc<SyncParseFn<A, E, S>>);
+
+// not derived, as that would need A and E to be Clone
+impl<A, E, S: ?Sized> Clone for SyncParser<A, E, S> {
+    fn clone(&self) -> Self {
+        SyncParser(Arc::clone(&self.0))
+    }
+}
+
+impl<A, E, S: Source + ?Sized> Parse<S> for SyncParser<A, E, S> {
+    type Output = A;
+    type Error = E;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(A, Input<'a, S>), E> {
+        (self.0)(inp)
+    }
+}
+
+impl<A, E, S: Source + ?Sized> Parse<S> for Parser<A, E, S> {
+    type Output = A;
+    type Error = E;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(A, Input<'a, S>), E> {
+        (self.0)(inp)
+    }
+}
+
+// primitives, all for text
+
+// like p_char. the error they fail with is the E these are given, ParseError unless said
+// otherwise. it's held as a fn() -> E so that sharing them doesn't depend on what E is
+pub fn char<E: ParserError>(c: char) -> Char<E> {
+    Char(c, PhantomData)
+}
+
+pub struct Char<E = ParseError>(char, PhantomData<fn() -> E>);
+
+impl<E: ParserError> Parse for Char<E> {
+    type Output = char;
+    type Error = E;
+
+    fn parse_input<'a>(&self, inp: Input<'a>) -> Result<(char, Input<'a>), E> {
+        let c = self.0;
+        match inp.rest.chars().next() {
+            Some(next) if next == c => Ok((c, inp.advance(c.len_utf8()))),
+            Some(wrong) => Err(mismatch(
+                inp.offset,
+                Expected::Literal(c.to_string()),
+                &wrong.to_string(),
+            )),
+            None => Err(inp.ended(Expected::Literal(c.to_string()), Needed::Size(c.len_utf8()))),
+        }
+    }
+}
+
+// like p_str, without copying s for each match
+pub fn literal<E: ParserError>(s: &'static str) -> Literal<E> {
+    Literal(s, PhantomData)
+}
+
+pub struct Literal<E = ParseError>(&'static str, PhantomData<fn() -> E>);
+
+impl<E: ParserError> Parse for Literal<E> {
+    type Output = &'static str;
+    type Error = E;
+
+    fn parse_input<'a>(&self, inp: Input<'a>) -> Result<(&'static str, Input<'a>), E> {
+        let s = self.0;
+        match inp.rest.strip_prefix(s) {
+            Some(remaining) => Ok((s, inp.skip_to(remaining))),
+            None if inp.streaming && s.starts_with(inp.rest) => Err(inp.ended(
+                Expected::Literal(s.to_string()),
+                Needed::Size(s.len() - inp.rest.len()),
+            )),
+            None => Err(mismatch(
+                inp.offset,
+                Expected::Literal(s.to_string()),
+                inp.rest,
+            )),
+        }
+    }
+}
+
+// like satisfy
+pub fn satisfy<F: Fn(char) -> bool, E: ParserError>(pred: F) -> Satisfy<F, E> {
+    Satisfy(pred, PhantomData)
+}
+
+pub struct Satisfy<F, E = ParseError>(F, PhantomData<fn() -> E>);
+
+impl<F: Fn(char) -> bool, E: ParserError> Parse for Satisfy<F, E> {
+    type Output = char;
+    type Error = E;
+
+    fn parse_input<'a>(&self, inp: Input<'a>) -> Result<(char, Input<'a>), E> {
+        match inp.rest.chars().next() {
+            Some(c) if (self.0)(c) => Ok((c, inp.advance(c.len_utf8()))),
+            _ => Err(unexpected(
+                Expected::Class("matching character".to_string()),
+                inp,
+            )),
+        }
+    }
+}
+
+// like take_while and take_while1
+pub fn take_while<F: Fn(char) -> bool, E: ParserError>(pred: F) -> TakeWhile<F, E> {
+    TakeWhile {
+        pred,
+        min: 0,
+        error: PhantomData,
+    }
+}
+
+pub fn take_while1<F: Fn(char) -> bool, E: ParserError>(pred: F) -> TakeWhile<F, E> {
+    TakeWhile {
+        pred,
+        min: 1,
+        error: PhantomData,
+    }
+}
+
+pub struct TakeWhile<F, E = ParseError> {
+    pred: F,
+    min: usize,
+    error: PhantomData<fn() -> E>,
+}
+
+impl<F: Fn(char) -> bool, E: ParserError> Parse for TakeWhile<F, E> {
+    type Output = Span;
+    type Error = E;
+
+    fn parse_input<'a>(&self, inp: Input<'a>) -> Result<(Span, Input<'a>), E> {
+        match prefix_len(inp.rest, &self.pred) {
+            // when streaming, what arrives next might match too
+            len if inp.streaming && len == inp.rest.len() => Err(inp.incomplete()),
+            len if len < self.min => Err(unexpected(
+                Expected::Class("matching character".to_string()),
+                inp,
+            )),
+            len => {
+                let span = Span {
+                    start: inp.offset,
+                    end: inp.offset + len,
+                };
+                Ok((span, inp.advance(len)))
+            }
+        }
+    }
+}
+
+// combinators
+
+pub struct Map<P, F>(P, F);
+
+impl<S: Source + ?Sized, P: Parse<S>, B, F: Fn(P::Output) -> B> Parse<S> for Map<P, F> {
+    type Output = B;
+    type Error = P::Error;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(B, Input<'a, S>), P::Error> {
+        let (r, rest) = self.0.parse_input(inp)?;
+        Ok(((self.1)(r), rest))
+    }
+}
+
+pub struct Then<P, Q>(P, Q);
+
+impl<S: Source + ?Sized, P: Parse<S>, Q: Parse<S, Error = P::Error>> Parse<S> for Then<P, Q> {
+    type Output = Q::Output;
+    type Error = P::Error;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(Q::Output, Input<'a, S>), P::Error> {
+        let (_, rest) = self.0.parse_input(inp)?;
+        self.1.parse_input(rest)
+    }
+}
+
+pub struct Skip<P, Q>(P, Q);
+
+impl<S: Source + ?Sized, P: Parse<S>, Q: Parse<S, Error = P::Error>> Parse<S> for Skip<P, Q> {
+    type Output = P::Output;
+    type Error = P::Error;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(P::Output, Input<'a, S>), P::Error> {
+        let (r, rest) = self.0.parse_input(inp)?;
+        let (_, rest) = self.1.parse_input(rest)?;
+        Ok((r, rest))
+    }
+}
+
+pub struct Pair<P, Q>(P, Q);
+
+impl<S: Source + ?Sized, P: Parse<S>, Q: Parse<S, Error = P::Error>> Parse<S> for Pair<P, Q> {
+    type Output = (P::Output, Q::Output);
+    type Error = P::Error;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(Self::Output, Input<'a, S>), P::Error> {
+        let (ra, rest) = self.0.parse_input(inp)?;
+        let (rb, rest) = self.1.parse_input(rest)?;
+        Ok(((ra, rb), rest))
+    }
+}
+
+pub struct Or<P, Q>(P, Q);
+
+impl<S, P, Q> Parse<S> for Or<P, Q>
+where
+    S: Source + ?Sized,
+    P: Parse<S, Error: ParserError>,
+    Q: Parse<S, Output = P::Output, Error = P::Error>,
+{
+    type Output = P::Output;
+    type Error = P::Error;
+
+    // the same as p_or
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(P::Output, Input<'a, S>), P::Error> {
+        let mark = inp.mark();
+        match self.0.parse_input(inp) {
+            Ok(a) => Ok(a),
+            Err(e) if e.is_cut() => Err(e),
+            Err(e) => {
+                inp.rewind(mark);
+                match self.1.parse_input(inp) {
+                    Ok(b) => Ok(b),
+                    Err(e2) if e2.is_cut() => Err(e2),
+                    Err(e2) => Err(P::Error::merge(vec![e, e2])),
+                }
+            }
+        }
+    }
+}
+
+pub struct Opt<P>(P);
+
+impl<S: Source + ?Sized, P: Parse<S, Error: ParserError>> Parse<S> for Opt<P> {
+    type Output = Option<P::Output>;
+    type Error = P::Error;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(Self::Output, Input<'a, S>), P::Error> {
+        let mark = inp.mark();
+        match self.0.parse_input(inp) {
+            Ok((r, rest)) => Ok((Some(r), rest)),
+            Err(e) if e.is_cut() => Err(e),
+            Err(_) => {
+                inp.rewind(mark);
+                Ok((None, inp))
+            }
+        }
+    }
+}
+
+// p at least min times, as many times as it matches
+pub struct Many<P> {
+    p: P,
+    min: usize,
+}
+
+impl<S: Source + ?Sized, P: Parse<S, Error: ParserError>> Parse<S> for Many<P> {
+    type Output = Vec<P::Output>;
+    type Error = P::Error;
+
+    fn parse_input<'a>(
+        &self,
+        mut inp: Input<'a, S>,
+    ) -> Result<(Self::Output, Input<'a, S>), P::Error> {
+        let mut results = Vec::new();
+        loop {
+            let mark = inp.mark();
+            match self.p.parse_input(inp) {
+                Ok((r, rest)) if rest.offset > inp.offset => {
+                    results.push(r);
+                    inp = rest;
+                }
+                // p would match forever without consuming anything, so it's only taken as
+                // many times as min needs, like repeat does
+                Ok((r, rest)) if results.len() < self.min => {
+                    results.push(r);
+                    inp = rest;
+                }
+                Err(e) if e.is_cut() || results.len() < self.min => return Err(e),
+                _ => {
+                    inp.rewind(mark);
+                    break;
+                }
+            }
+        }
+        Ok((results, inp))
+    }
+}
+
+// like recognize_span
+pub struct Recognize<P>(P);
+
+impl<S: Source + ?Sized, P: Parse<S>> Parse<S> for Recognize<P> {
+    type Output = Span;
+    type Error = P::Error;
+
+    fn parse_input<'a>(&self, inp: Input<'a, S>) -> Result<(Span, Input<'a, S>), P::Error> {
+        let (_, rest) = self.0.parse_input(inp)?;
+        let span = Span {
+            start: inp.offset,
+            end: rest.offset,
+        };
+        Ok((span, rest))
+    }
+}
@@ -0,0 +1,7 @@
+// what writing a grammar takes, so that `use rparsec::prelude::*` is the only use needed
+
+pub use crate::combinator::*;
+pub use crate::error::{Expected, ParseError, ParserError, Position};
+pub use crate::parse::Parse;
+pub use crate::primitive::*;
+pub use crate::{Input, IntoParser, Parser, Source};
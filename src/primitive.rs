@@ -0,0 +1,1077 @@
+// primitives, the parsers that everything else is built from
+//
+// the text ones here give back what they matched as a String. a Parser's result can't
+// borrow from the input, so the nearest there is to a &str of it is a Span, which Span::of
+// slices the input with. p_str and the take ones each have a _span version that gives that
+// instead, for where the copies matter, as in a lexer's inner loop. making one allocates
+// nothing, and neither does lexeme skipping what comes after
+
+use alloc::format;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Display};
+use core::ops::RangeInclusive;
+use core::str::FromStr;
+
+#[cfg(feature = "regex")]
+use core::cell::RefCell;
+#[cfg(feature = "regex")]
+use regex_automata::hybrid;
+#[cfg(feature = "graphemes")]
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::combinator::*;
+use crate::error::*;
+use crate::{Input, IntoParser, Parser, Source};
+
+// like pure, succeeds with value without consuming anything
+pub fn pure<A: Clone + 'static, E: 'static, S: Source + ?Sized>(value: A) -> Parser<A, E, S> {
+    Parser(Rc::new(move |inp: Input<S>| Ok((value.clone(), inp))))
+}
+
+// always fails with error
+pub fn fail<A: 'static, E: Clone + 'static, S: Source + ?Sized>(error: E) -> Parser<A, E, S> {
+    Parser(Rc::new(move |_: Input<S>| Err(error.clone())))
+}
+
+// a mismatch of expected for found at offset, like ParseError::mismatch
+pub(crate) fn mismatch<E: ParserError>(offset: usize, expected: Expected, found: &str) -> E {
+    let found = first_line(found);
+    E::from_mismatch(offset..offset + found.len(), expected, found)
+}
+
+// error for when `expected` was wanted but the next char of inp (if any) didn't match
+pub(crate) fn unexpected<E: ParserError, S: Source + ?Sized>(
+    expected: Expected,
+    inp: Input<S>,
+) -> E {
+    match inp.rest.first_len() {
+        Some(n) => {
+            let wrong = &inp.rest[..n];
+            E::from_mismatch(inp.offset..inp.offset + n, expected, &wrong.describe())
+        }
+        None => inp.ended(expected, Needed::Size(1)),
+    }
+}
+
+pub fn p_char<E: ParserError + 'static>(c: char) -> Parser<char, E> {
+    Parser(Rc::new(move |inp: Input| {
+        let mut chars = inp.rest.chars();
+        let next = chars.next();
+        match next {
+            Some(c_) if c_ == c => Ok((c, inp.advance(c.len_utf8()))),
+            Some(wrong) => Err(mismatch(
+                inp.offset,
+                Expected::Literal(c.to_string()),
+                &wrong.to_string(),
+            )),
+            None => Err(inp.ended(Expected::Literal(c.to_string()), Needed::Size(c.len_utf8()))),
+        }
+    }))
+}
+
+// consumes one char satisfying pred, failing with the class `expected` otherwise
+pub fn satisfy_named<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    expected: impl Into<String>,
+    pred: F,
+) -> Parser<char, E> {
+    let expected = Expected::Class(expected.into());
+    Parser(Rc::new(move |inp: Input| match inp.rest.chars().next() {
+        Some(c) if pred(c) => Ok((c, inp.advance(c.len_utf8()))),
+        _ => Err(unexpected(expected.clone(), inp)),
+    }))
+}
+
+pub fn satisfy<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    pred: F,
+) -> Parser<char, E> {
+    satisfy_named("matching character", pred)
+}
+
+// consumes any one char, only failing at the end of the input
+pub fn any_char<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("any character", |_| true)
+}
+
+// a set of chars with constant-time lookup for ascii
+struct CharSet {
+    ascii: u128,
+    other: Vec<char>,
+}
+
+impl CharSet {
+    fn new(chars: &str) -> CharSet {
+        let mut set = CharSet {
+            ascii: 0,
+            other: Vec::new(),
+        };
+        for c in chars.chars() {
+            if c.is_ascii() {
+                set.ascii |= 1 << c as u32;
+            } else {
+                set.other.push(c);
+            }
+        }
+        set
+    }
+
+    fn contains(&self, c: char) -> bool {
+        if c.is_ascii() {
+            self.ascii & (1 << c as u32) != 0
+        } else {
+            self.other.contains(&c)
+        }
+    }
+}
+
+// consumes one char that is in chars
+pub fn one_of<E: ParserError + 'static>(chars: &str) -> Parser<char, E> {
+    let set = CharSet::new(chars);
+    satisfy_named(format!("one of {:?}", chars), move |c| set.contains(c))
+}
+
+// consumes one char that isn't in chars
+pub fn none_of<E: ParserError + 'static>(chars: &str) -> Parser<char, E> {
+    let set = CharSet::new(chars);
+    satisfy_named(format!("none of {:?}", chars), move |c| !set.contains(c))
+}
+
+// consumes one char within range
+pub fn char_range<E: ParserError + 'static>(range: RangeInclusive<char>) -> Parser<char, E> {
+    satisfy_named(format!("{:?}", range), move |c| range.contains(&c))
+}
+
+// where in the input a parser that started at inp has got to by rest
+fn span_to(inp: Input, rest: Input) -> Span {
+    Span {
+        start: inp.offset,
+        end: rest.offset,
+    }
+}
+
+pub fn p_str<E: ParserError + 'static>(s: String) -> Parser<String, E> {
+    recognize(p_str_span(&s))
+}
+
+pub fn p_str_span<E: ParserError + 'static>(s: &str) -> Parser<Span, E> {
+    let s = s.to_string();
+    Parser(Rc::new(move |inp: Input| {
+        match inp.rest.strip_prefix(s.as_str()) {
+            Some(remaining) => {
+                let rest = inp.skip_to(remaining);
+                Ok((span_to(inp, rest), rest))
+            }
+            None if inp.streaming && s.starts_with(inp.rest) => Err(inp.ended(
+                Expected::Literal(s.to_string()),
+                Needed::Size(s.len() - inp.rest.len()),
+            )),
+            None => Err(mismatch(
+                inp.offset,
+                Expected::Literal(s.to_string()),
+                inp.rest,
+            )),
+        }
+    }))
+}
+
+// like p_str but ignores case, returning the input as it was written
+pub fn p_str_no_case<E: ParserError + 'static>(s: &str) -> Parser<String, E> {
+    let s = s.to_string();
+    Parser(Rc::new(move |inp: Input| {
+        let mut chars = inp.rest.chars();
+        for expected in s.chars() {
+            match chars.next() {
+                Some(c) if c.to_lowercase().eq(expected.to_lowercase()) => {}
+                // when streaming, the rest of it might still arrive
+                None if inp.streaming => {
+                    return Err(inp.ended(Expected::Literal(s.clone()), Needed::Unknown))
+                }
+                _ => return Err(mismatch(inp.offset, Expected::Literal(s.clone()), inp.rest)),
+            }
+        }
+        let rest = inp.skip_to(chars.as_str());
+        Ok((consumed_by(inp, rest).to_string(), rest))
+    }))
+}
+
+// length in bytes of the longest prefix of inp whose chars all satisfy pred
+pub(crate) fn prefix_len(inp: &str, pred: impl Fn(char) -> bool) -> usize {
+    inp.find(|c: char| !pred(c)).unwrap_or(inp.len())
+}
+
+pub fn take_while<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    pred: F,
+) -> Parser<String, E> {
+    recognize(take_while_span(pred))
+}
+
+pub fn take_while_span<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    pred: F,
+) -> Parser<Span, E> {
+    Parser(Rc::new(move |inp: Input| {
+        let len = prefix_len(inp.rest, &pred);
+        // when streaming, what arrives next might match too
+        if inp.streaming && len == inp.rest.len() {
+            return Err(inp.incomplete());
+        }
+        let rest = inp.advance(len);
+        Ok((span_to(inp, rest), rest))
+    }))
+}
+
+pub fn take_while1<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    pred: F,
+) -> Parser<String, E> {
+    take_while1_named("matching character", pred)
+}
+
+pub fn take_while1_span<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    pred: F,
+) -> Parser<Span, E> {
+    take_while1_named_span("matching character", pred)
+}
+
+// like take_while1, failing with the class `expected` when nothing matches
+pub fn take_while1_named<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    expected: impl Into<String>,
+    pred: F,
+) -> Parser<String, E> {
+    recognize(take_while1_named_span(expected, pred))
+}
+
+pub fn take_while1_named_span<F: Fn(char) -> bool + 'static, E: ParserError + 'static>(
+    expected: impl Into<String>,
+    pred: F,
+) -> Parser<Span, E> {
+    let expected = Expected::Class(expected.into());
+    Parser(Rc::new(move |inp: Input| {
+        match prefix_len(inp.rest, &pred) {
+            len if inp.streaming && len == inp.rest.len() => Err(inp.incomplete()),
+            0 => Err(unexpected(expected.clone(), inp)),
+            len => {
+                let rest = inp.advance(len);
+                Ok((span_to(inp, rest), rest))
+            }
+        }
+    }))
+}
+
+// character classes, all ascii only
+
+pub fn digit<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("digit", |c| c.is_ascii_digit())
+}
+
+pub fn digit1<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while1_named("digit", |c| c.is_ascii_digit())
+}
+
+pub fn alpha<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("letter", |c| c.is_ascii_alphabetic())
+}
+
+pub fn alpha1<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while1_named("letter", |c| c.is_ascii_alphabetic())
+}
+
+pub fn alphanumeric<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("letter or digit", |c| c.is_ascii_alphanumeric())
+}
+
+pub fn alphanumeric1<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while1_named("letter or digit", |c| c.is_ascii_alphanumeric())
+}
+
+pub fn whitespace<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("whitespace", |c| c.is_ascii_whitespace())
+}
+
+pub fn whitespace1<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while1_named("whitespace", |c| c.is_ascii_whitespace())
+}
+
+// numbers
+
+// an optionally signed decimal integer, stopping at the first non-digit.
+// a value that doesn't fit in T is a parse error
+pub fn int<T: FromStr + 'static, E: ParserError + 'static>() -> Parser<T, E>
+where
+    T::Err: Display,
+{
+    map_res(recognize(pair(opt(one_of("+-")), digit1())), |s| s.parse())
+}
+
+// an unsigned decimal integer, stopping at the first non-digit.
+// a value that doesn't fit in T is a parse error
+pub fn uint<T: FromStr + 'static, E: ParserError + 'static>() -> Parser<T, E>
+where
+    T::Err: Display,
+{
+    map_res(digit1(), |s| s.parse())
+}
+
+// how the digits of a number may be split up, e.g. 1_000_000
+pub struct DigitSeparators {
+    pub separator: char,
+    // if set, every group after the first must have exactly this many digits
+    // and the first can't have more
+    pub group_size: Option<usize>,
+}
+
+impl Default for DigitSeparators {
+    fn default() -> Self {
+        DigitSeparators {
+            separator: '_',
+            group_size: None,
+        }
+    }
+}
+
+// decimal digits split into groups by separators, returning just the digits.
+// a leading, trailing or doubled separator, or a wrongly sized group, is an error
+pub fn separated_digits<E: ParserError + 'static>(config: DigitSeparators) -> Parser<String, E> {
+    Parser(Rc::new(move |inp: Input| {
+        let is_digit = |c: char| c.is_ascii_digit();
+        let first = prefix_len(inp.rest, is_digit);
+        if first == 0 {
+            return Err(unexpected(Expected::Class("digit".to_string()), inp));
+        }
+        let mut digits = inp.rest[..first].to_string();
+        let mut rest = inp.advance(first);
+        let mut well_grouped = config.group_size.is_none_or(|size| first <= size);
+        while rest.rest.starts_with(config.separator) {
+            let after_sep = rest.advance(config.separator.len_utf8());
+            let group = prefix_len(after_sep.rest, is_digit);
+            if group == 0 && inp.streaming && after_sep.rest.is_empty() {
+                return Err(inp.incomplete());
+            }
+            if group == 0 {
+                let bad = consumed_by(inp, after_sep);
+                return Err(E::from_invalid(inp.offset, bad));
+            }
+            well_grouped &= config.group_size.is_none_or(|size| group == size);
+            digits.push_str(&after_sep.rest[..group]);
+            rest = after_sep.advance(group);
+        }
+        // when streaming, more digits or separators might be on their way
+        if inp.streaming && rest.rest.is_empty() {
+            return Err(inp.incomplete());
+        }
+        if !well_grouped {
+            return Err(E::from_invalid(inp.offset, consumed_by(inp, rest)));
+        }
+        Ok((digits, rest))
+    }))
+}
+
+// an unsigned decimal integer whose digits may be split up by separators
+pub fn separated_uint<T: FromStr + 'static, E: ParserError + 'static>(
+    config: DigitSeparators,
+) -> Parser<T, E>
+where
+    T::Err: Display,
+{
+    map_res(separated_digits(config), |s| s.parse())
+}
+
+// an unsigned integer in the given radix, without any prefix.
+// panics if radix isn't in 2..=36, like u32::from_str_radix
+pub fn int_radix<E: ParserError + 'static>(radix: u32) -> Parser<u32, E> {
+    assert!(
+        (2..=36).contains(&radix),
+        "radix must be in 2..=36, got {}",
+        radix
+    );
+    let digits = take_while1_named(format!("base {} digit", radix), move |c| c.is_digit(radix));
+    map_res(digits, move |s| u32::from_str_radix(&s, radix))
+}
+
+pub fn hex_u32<E: ParserError + 'static>() -> Parser<u32, E> {
+    int_radix(16)
+}
+
+pub fn oct_u32<E: ParserError + 'static>() -> Parser<u32, E> {
+    int_radix(8)
+}
+
+pub fn bin_u32<E: ParserError + 'static>() -> Parser<u32, E> {
+    int_radix(2)
+}
+
+// length in bytes of the float literal at the start of inp, following the grammar of f64::from_str:
+// an optional sign, then inf, infinity or nan in any case, or digits with an optional
+// decimal point and exponent
+fn float_len(inp: &str) -> Option<usize> {
+    let bytes = inp.as_bytes();
+    let digits_at = |i: usize| {
+        bytes
+            .iter()
+            .skip(i)
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let sign_at = |i: usize| usize::from(matches!(bytes.get(i), Some(b'+' | b'-')));
+
+    let mut i = sign_at(0);
+    for special in ["infinity", "inf", "nan"] {
+        if let Some(word) = bytes.get(i..i + special.len()) {
+            if word.eq_ignore_ascii_case(special.as_bytes()) {
+                return Some(i + special.len());
+            }
+        }
+    }
+
+    let int_digits = digits_at(i);
+    i += int_digits;
+    let mut frac_digits = 0;
+    if bytes.get(i) == Some(&b'.') {
+        frac_digits = digits_at(i + 1);
+        if int_digits + frac_digits > 0 {
+            i += 1 + frac_digits;
+        }
+    }
+    if int_digits + frac_digits == 0 {
+        return None;
+    }
+
+    // an exponent is only consumed if it has digits
+    if matches!(bytes.get(i), Some(b'e' | b'E')) {
+        let exp_start = i + 1 + sign_at(i + 1);
+        let exp_digits = digits_at(exp_start);
+        if exp_digits > 0 {
+            i = exp_start + exp_digits;
+        }
+    }
+    Some(i)
+}
+
+// whether all of s could be the start of a float literal that carries on past it, so that
+// when streaming there's no telling what it is until more arrives
+fn float_prefix(s: &str) -> bool {
+    let is_digit = |c: char| c.is_ascii_digit();
+    let s = s.strip_prefix(['+', '-']).unwrap_or(s);
+    let word_prefix = |word: &str| {
+        word.len() > s.len() && word.as_bytes()[..s.len()].eq_ignore_ascii_case(s.as_bytes())
+    };
+    if word_prefix("infinity") || word_prefix("nan") {
+        return true;
+    }
+    let s = s.trim_start_matches(is_digit);
+    let s = s
+        .strip_prefix('.')
+        .unwrap_or(s)
+        .trim_start_matches(is_digit);
+    let Some(exp) = s.strip_prefix(['e', 'E']) else {
+        return s.is_empty();
+    };
+    let exp = exp.strip_prefix(['+', '-']).unwrap_or(exp);
+    exp.trim_start_matches(is_digit).is_empty()
+}
+
+pub fn float<T: FromStr + 'static, E: ParserError + 'static>() -> Parser<T, E>
+where
+    T::Err: Display,
+{
+    Parser(Rc::new(move |inp: Input| {
+        if inp.streaming && float_prefix(inp.rest) {
+            return Err(inp.incomplete());
+        }
+        match float_len(inp.rest) {
+            Some(len) => {
+                let literal = &inp.rest[..len];
+                match literal.parse() {
+                    Ok(f) => Ok((f, inp.advance(len))),
+                    Err(e) => Err(E::from_conversion(inp.offset, literal, &e.to_string())),
+                }
+            }
+            None => Err(unexpected(Expected::Label("float".to_string()), inp)),
+        }
+    }))
+}
+
+// strings
+
+// recognizes a run of `normal` input and escape sequences, where an escape sequence is
+// escape_char followed by something `escapable` accepts. returns the raw text, escapes included
+pub fn escaped<A: 'static, B: 'static, E: ParserError + 'static>(
+    normal: impl IntoParser<E, Output = A>,
+    escape_char: char,
+    escapable: impl IntoParser<E, Output = B>,
+) -> Parser<String, E> {
+    let normal = normal.into_parser();
+    let escapable = escapable.into_parser();
+    Parser(Rc::new(move |inp: Input| {
+        let mut rest = inp;
+        loop {
+            let mark = rest.mark();
+            match (normal.0)(rest) {
+                Ok((_, after)) if after.offset > rest.offset => {
+                    rest = after;
+                    continue;
+                }
+                Err(e) if e.is_cut() => return Err(e),
+                _ => rest.rewind(mark),
+            }
+            match rest.rest.strip_prefix(escape_char) {
+                Some(after) => rest = (escapable.0)(rest.skip_to(after))?.1,
+                None => break,
+            }
+        }
+        Ok((consumed_by(inp, rest).to_string(), rest))
+    }))
+}
+
+// how quoted_string reads a literal
+pub struct QuoteConfig {
+    // chars that can open a string, which is then closed by the same char
+    pub quotes: Vec<char>,
+    pub escape_char: char,
+    // what each char after escape_char decodes to, e.g. ('n', '\n')
+    pub escapes: Vec<(char, char)>,
+    // whether \u{1F600} style code point escapes are decoded
+    pub unicode_escapes: bool,
+}
+
+impl Default for QuoteConfig {
+    // double quoted strings with rust's escapes
+    fn default() -> Self {
+        QuoteConfig {
+            quotes: vec!['"'],
+            escape_char: '\\',
+            escapes: vec![
+                ('n', '\n'),
+                ('r', '\r'),
+                ('t', '\t'),
+                ('0', '\0'),
+                ('\\', '\\'),
+                ('"', '"'),
+                ('\'', '\''),
+            ],
+            unicode_escapes: true,
+        }
+    }
+}
+
+// decodes the body of a \u{...} escape, with inp just after the u
+fn unicode_escape<E: ParserError>(inp: Input) -> Result<(char, Input), E> {
+    if !inp.rest.starts_with('{') {
+        return Err(unexpected(Expected::Literal("{".to_string()), inp));
+    }
+    let body = inp.advance(1);
+    let len = prefix_len(body.rest, |c| c.is_ascii_hexdigit());
+    let (digits, rest) = (&body.rest[..len], body.advance(len));
+    if digits.is_empty() {
+        return Err(unexpected(Expected::Class("hex digit".to_string()), rest));
+    }
+    if !rest.rest.starts_with('}') {
+        return Err(unexpected(Expected::Literal("}".to_string()), rest));
+    }
+    let rest = rest.advance(1);
+    // at most 6 digits, and it has to be a valid code point
+    let c = Some(digits)
+        .filter(|d| d.len() <= 6)
+        .and_then(|d| char::from_u32(u32::from_str_radix(d, 16).unwrap()));
+    match c {
+        Some(c) => Ok((c, rest)),
+        None => Err(E::from_invalid(inp.offset, consumed_by(inp, rest))),
+    }
+}
+
+// a quoted string literal, returning its contents with escapes decoded
+pub fn quoted_string<E: ParserError + 'static>(config: QuoteConfig) -> Parser<String, E> {
+    Parser(Rc::new(move |inp: Input| {
+        let mut chars = inp.rest.chars();
+        let quote = match chars.next() {
+            Some(q) if config.quotes.contains(&q) => q,
+            _ => return Err(unexpected(Expected::Label("quote".to_string()), inp)),
+        };
+        // when the quote doubles as the escape char ('it''s'), a quote only closes the
+        // string if it isn't the start of an escape
+        let is_escape = |after: &str| {
+            let next = after.chars().next();
+            config.escapes.iter().any(|(from, _)| Some(*from) == next)
+        };
+        let mut decoded = String::new();
+        loop {
+            match chars.next() {
+                None => {
+                    return Err(inp.ended(Expected::Literal(quote.to_string()), Needed::Unknown))
+                }
+                Some(c)
+                    if c == quote && (c != config.escape_char || !is_escape(chars.as_str())) =>
+                {
+                    return Ok((decoded, inp.skip_to(chars.as_str())))
+                }
+                Some(c) if c == config.escape_char => {}
+                Some(c) => {
+                    decoded.push(c);
+                    continue;
+                }
+            }
+            let after_escape = chars.as_str();
+            match chars.next() {
+                Some('u') if config.unicode_escapes => {
+                    let (c, after) = unicode_escape(inp.skip_to(chars.as_str()))?;
+                    decoded.push(c);
+                    chars = after.rest.chars();
+                }
+                Some(e) => match config.escapes.iter().find(|(from, _)| *from == e) {
+                    Some((_, to)) => decoded.push(*to),
+                    None => {
+                        return Err(unexpected(
+                            Expected::Label("escape sequence".to_string()),
+                            inp.skip_to(after_escape),
+                        ))
+                    }
+                },
+                None => {
+                    return Err(inp.ended(
+                        Expected::Label("escape sequence".to_string()),
+                        Needed::Unknown,
+                    ))
+                }
+            }
+        }
+    }))
+}
+
+// identifiers
+
+// [A-Za-z_][A-Za-z0-9_]*
+pub fn identifier<E: ParserError + 'static>() -> Parser<String, E> {
+    identifier_with(
+        |c| c.is_ascii_alphabetic() || c == '_',
+        |c| c.is_ascii_alphanumeric() || c == '_',
+    )
+}
+
+// the literal kw, as long as it isn't just the start of a longer identifier
+pub fn keyword<E: ParserError + 'static>(kw: &str) -> Parser<String, E> {
+    terminated(
+        p_str(kw.to_string()),
+        not(satisfy(|c| c.is_ascii_alphanumeric() || c == '_')),
+    )
+}
+
+// one char satisfying start followed by any number satisfying cont
+pub fn identifier_with<S, C, E: ParserError + 'static>(start: S, cont: C) -> Parser<String, E>
+where
+    S: Fn(char) -> bool + 'static,
+    C: Fn(char) -> bool + 'static,
+{
+    Parser(Rc::new(move |inp: Input| match inp.rest.chars().next() {
+        Some(c) if start(c) => {
+            let tail = inp.advance(c.len_utf8());
+            let len = prefix_len(tail.rest, &cont);
+            // when streaming, what arrives next might carry it on
+            if inp.streaming && len == tail.rest.len() {
+                return Err(inp.incomplete());
+            }
+            let rest = tail.advance(len);
+            Ok((consumed_by(inp, rest).to_string(), rest))
+        }
+        _ => Err(unexpected(Expected::Label("identifier".to_string()), inp)),
+    }))
+}
+
+// unicode identifiers, as defined by UAX #31
+
+#[cfg(feature = "unicode-ident")]
+pub fn xid_start<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("identifier start", unicode_ident::is_xid_start)
+}
+
+#[cfg(feature = "unicode-ident")]
+pub fn xid_continue<E: ParserError + 'static>() -> Parser<char, E> {
+    satisfy_named("identifier character", unicode_ident::is_xid_continue)
+}
+
+// an XID_Start char or _, followed by any number of XID_Continue chars, like rust identifiers
+#[cfg(feature = "unicode-ident")]
+pub fn unicode_ident<E: ParserError + 'static>() -> Parser<String, E> {
+    identifier_with(
+        |c| c == '_' || unicode_ident::is_xid_start(c),
+        unicode_ident::is_xid_continue,
+    )
+}
+
+// graphemes, as in what someone reading the text would call one character. they're the
+// extended grapheme clusters of unicode-segmentation
+
+// length in bytes of the grapheme s starts with, if it has one
+#[cfg(feature = "graphemes")]
+fn grapheme_len(s: &str) -> Option<usize> {
+    s.graphemes(true).next().map(str::len)
+}
+
+// like any_char, for a grapheme
+#[cfg(feature = "graphemes")]
+pub fn any_grapheme<E: ParserError + 'static>() -> Parser<String, E> {
+    take_graphemes(1)
+}
+
+// consumes exactly n graphemes
+#[cfg(feature = "graphemes")]
+pub fn take_graphemes<E: ParserError + 'static>(n: usize) -> Parser<String, E> {
+    Parser(Rc::new(move |inp: Input| {
+        let mut rest = inp;
+        for _ in 0..n {
+            let Some(len) = grapheme_len(rest.rest) else {
+                let expected = match n {
+                    1 => "a grapheme".to_string(),
+                    n => format!("{} graphemes", n),
+                };
+                return Err(rest.ended(Expected::Label(expected), Needed::Unknown));
+            };
+            // when streaming, what arrives next might be part of the last grapheme
+            if inp.streaming && len == rest.rest.len() {
+                return Err(rest.incomplete());
+            }
+            rest = rest.advance(len);
+        }
+        Ok((consumed_by(inp, rest).to_string(), rest))
+    }))
+}
+
+// regular expressions
+
+// pattern, compiled so that it only matches at the start of the input. the lazy dfa is
+// only run when streaming, to tell whether more input could change what's matched
+#[cfg(feature = "regex")]
+struct AnchoredRegex {
+    re: regex::Regex,
+    dfa: Option<(hybrid::dfa::DFA, RefCell<hybrid::dfa::Cache>)>,
+}
+
+#[cfg(feature = "regex")]
+impl AnchoredRegex {
+    // panics if pattern isn't a valid regex
+    fn new(pattern: &str) -> AnchoredRegex {
+        let anchored = format!("^(?:{})", pattern);
+        let re = match regex::Regex::new(&anchored) {
+            Ok(re) => re,
+            Err(e) => panic!("invalid regex {:?}: {}", pattern, e),
+        };
+        let config = hybrid::dfa::DFA::config().unicode_word_boundary(true);
+        let dfa = hybrid::dfa::DFA::builder()
+            .configure(config)
+            .build(&anchored)
+            .ok()
+            .map(|dfa| {
+                let cache = RefCell::new(dfa.create_cache());
+                (dfa, cache)
+            });
+        AnchoredRegex { re, dfa }
+    }
+
+    // whether what arrives after s could change what's matched at its start. it can unless
+    // the dfa dies before the end of s. when the dfa can't say, as when it gives up at a
+    // unicode word boundary, only a match that reaches the end of s might go on
+    fn could_go_on(&self, s: &str) -> bool {
+        self.dfa_could_go_on(s)
+            .unwrap_or_else(|| self.re.find(s).is_some_and(|m| m.end() == s.len()))
+    }
+
+    fn dfa_could_go_on(&self, s: &str) -> Option<bool> {
+        let (dfa, cache) = self.dfa.as_ref()?;
+        let cache = &mut cache.borrow_mut();
+        let start = regex_automata::Input::new(s).anchored(regex_automata::Anchored::Yes);
+        let mut state = dfa.start_state_forward(cache, &start).ok()?;
+        for &b in s.as_bytes() {
+            if state.is_dead() || state.is_quit() {
+                break;
+            }
+            state = dfa.next_state(cache, state, b).ok()?;
+        }
+        if state.is_quit() {
+            return None;
+        }
+        Some(!state.is_dead())
+    }
+}
+
+// consumes the text matched by the regex pattern
+#[cfg(feature = "regex")]
+pub fn regex<E: ParserError + 'static>(pattern: &str) -> Parser<String, E> {
+    let re = AnchoredRegex::new(pattern);
+    let pattern = pattern.to_string();
+    Parser(Rc::new(move |inp: Input| {
+        if inp.streaming && re.could_go_on(inp.rest) {
+            return Err(inp.incomplete());
+        }
+        match re.re.find(inp.rest) {
+            Some(m) => Ok((m.as_str().to_string(), inp.advance(m.end()))),
+            None => Err(mismatch(
+                inp.offset,
+                Expected::Label(pattern.clone()),
+                inp.rest,
+            )),
+        }
+    }))
+}
+
+// like regex, but returns the capture groups. group 0 is the whole match
+#[cfg(feature = "regex")]
+pub fn regex_captures<E: ParserError + 'static>(pattern: &str) -> Parser<Vec<Option<String>>, E> {
+    let re = AnchoredRegex::new(pattern);
+    let pattern = pattern.to_string();
+    Parser(Rc::new(move |inp: Input| {
+        if inp.streaming && re.could_go_on(inp.rest) {
+            return Err(inp.incomplete());
+        }
+        match re.re.captures(inp.rest) {
+            Some(caps) => {
+                let end = caps.get(0).unwrap().end();
+                let groups = caps.iter().map(|g| g.map(|g| g.as_str().to_string()));
+                Ok((groups.collect(), inp.advance(end)))
+            }
+            None => Err(mismatch(
+                inp.offset,
+                Expected::Label(pattern.clone()),
+                inp.rest,
+            )),
+        }
+    }))
+}
+
+// spaces and tabs
+pub fn space0<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while(|c| c == ' ' || c == '\t')
+}
+
+pub fn space1<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while1_named("space", |c| c == ' ' || c == '\t')
+}
+
+// spaces, tabs and line endings
+pub fn multispace0<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while(|c| matches!(c, ' ' | '\t' | '\r' | '\n'))
+}
+
+pub fn multispace1<E: ParserError + 'static>() -> Parser<String, E> {
+    take_while1_named("whitespace", |c| matches!(c, ' ' | '\t' | '\r' | '\n'))
+}
+
+// line endings
+
+pub fn newline<E: ParserError + 'static>() -> Parser<char, E> {
+    p_char('\n')
+}
+
+pub fn crlf<E: ParserError + 'static>() -> Parser<String, E> {
+    p_str("\r\n".to_string())
+}
+
+// either \n or \r\n
+pub fn line_ending<E: ParserError + 'static>() -> Parser<String, E> {
+    Parser(Rc::new(move |inp: Input| {
+        for ending in ["\n", "\r\n"] {
+            if inp.rest.starts_with(ending) {
+                return Ok((ending.to_string(), inp.advance(ending.len())));
+            }
+        }
+        let expected = Expected::Label("line ending".to_string());
+        // when streaming, a \r at the end might be followed by its \n
+        if inp.streaming && "\r\n".starts_with(inp.rest) {
+            return Err(inp.ended(expected, Needed::Size(1)));
+        }
+        Err(unexpected(expected, inp))
+    }))
+}
+
+// tokens
+
+// runs `p` then skips any whitespace after it
+pub fn lexeme<A: 'static, E: ParserError + 'static>(
+    p: impl IntoParser<E, Output = A>,
+) -> Parser<A, E> {
+    terminated(
+        p,
+        take_while_span(|c| matches!(c, ' ' | '\t' | '\r' | '\n')),
+    )
+}
+
+// runs `p` then skips as many matches of `skip` as possible, e.g. whitespace or comments
+pub fn lexeme_with<A: 'static, W: 'static, E: ParserError + 'static, S: Source + ?Sized>(
+    p: impl IntoParser<E, S, Output = A>,
+    skip: impl IntoParser<E, S, Output = W>,
+) -> Parser<A, E, S> {
+    terminated(p, many0(skip))
+}
+
+// the literal s followed by any whitespace
+pub fn symbol<E: ParserError + 'static>(s: &str) -> Parser<String, E> {
+    lexeme(p_str(s.to_string()))
+}
+
+// consumes everything up to, but not including, the first occurrence of delim
+pub fn take_until<E: ParserError + 'static>(delim: &str) -> Parser<String, E> {
+    recognize(take_until_span(delim))
+}
+
+pub fn take_until_span<E: ParserError + 'static>(delim: &str) -> Parser<Span, E> {
+    let delim = delim.to_string();
+    Parser(Rc::new(move |inp: Input| match inp.rest.find(&delim) {
+        Some(i) => {
+            let rest = inp.advance(i);
+            Ok((span_to(inp, rest), rest))
+        }
+        None => Err(inp.ended(Expected::Literal(delim.clone()), Needed::Unknown)),
+    }))
+}
+
+// consumes exactly n chars
+pub fn take<E: ParserError + 'static>(n: usize) -> Parser<String, E> {
+    recognize(take_span(n))
+}
+
+pub fn take_span<E: ParserError + 'static>(n: usize) -> Parser<Span, E> {
+    Parser(Rc::new(move |inp: Input| {
+        let mut chars = inp.rest.chars();
+        for _ in 0..n {
+            if chars.next().is_none() {
+                return Err(inp.ended(
+                    Expected::Label(format!("{} characters", n)),
+                    Needed::Unknown,
+                ));
+            }
+        }
+        let rest = inp.skip_to(chars.as_str());
+        Ok((span_to(inp, rest), rest))
+    }))
+}
+
+// succeeds only at the end of the input
+pub fn eof<E: ParserError + 'static, S: Source + ?Sized>() -> Parser<(), E, S> {
+    Parser(Rc::new(move |inp: Input<S>| {
+        if inp.rest.len() == 0 {
+            // when streaming, more might still arrive
+            if inp.streaming {
+                return Err(inp.incomplete());
+            }
+            return Ok(((), inp));
+        }
+        let found = &inp.rest[..inp.rest.found_len()];
+        Err(E::from_mismatch(
+            inp.offset..inp.offset + found.len(),
+            Expected::EndOfInput,
+            &found.describe(),
+        ))
+    }))
+}
+
+// consumes and returns all remaining input, which when streaming can't be until all of it
+// has arrived
+pub fn rest<E: ParserError + 'static, S: Source + ?Sized>() -> Parser<S::Owned, E, S> {
+    Parser(Rc::new(move |inp: Input<S>| {
+        if inp.streaming {
+            return Err(inp.incomplete());
+        }
+        Ok((inp.rest.to_owned(), inp.advance(inp.rest.len())))
+    }))
+}
+
+// bytes
+
+// the byte b
+pub fn byte<E: ParserError + 'static>(b: u8) -> Parser<u8, E, [u8]> {
+    Parser(Rc::new(move |inp: Input<[u8]>| match inp.rest.first() {
+        Some(&c) if c == b => Ok((b, inp.advance(1))),
+        _ => Err(unexpected(Expected::Literal([b].describe()), inp)),
+    }))
+}
+
+// one byte satisfying pred
+pub fn satisfy_byte<F: Fn(u8) -> bool + 'static, E: ParserError + 'static>(
+    pred: F,
+) -> Parser<u8, E, [u8]> {
+    Parser(Rc::new(move |inp: Input<[u8]>| match inp.rest.first() {
+        Some(&c) if pred(c) => Ok((c, inp.advance(1))),
+        _ => Err(unexpected(
+            Expected::Class("matching byte".to_string()),
+            inp,
+        )),
+    }))
+}
+
+// exactly the bytes in bytes
+pub fn tag<E: ParserError + 'static>(bytes: &[u8]) -> Parser<Vec<u8>, E, [u8]> {
+    let bytes = bytes.to_vec();
+    Parser(Rc::new(move |inp: Input<[u8]>| {
+        if let Some(rest) = inp.rest.strip_prefix(bytes.as_slice()) {
+            return Ok((bytes.clone(), inp.skip_to(rest)));
+        }
+        let expected = Expected::Literal(bytes.describe());
+        // just as many bytes as were expected are shown as found
+        let found = &inp.rest[..inp.rest.len().min(bytes.len())];
+        match found {
+            _ if bytes.starts_with(found) && found.len() < bytes.len() && inp.streaming => {
+                Err(inp.ended(expected, Needed::Size(bytes.len() - found.len())))
+            }
+            [] => Err(inp.ended(expected, Needed::Size(bytes.len()))),
+            _ => Err(E::from_mismatch(
+                inp.offset..inp.offset + found.len(),
+                expected,
+                &found.describe(),
+            )),
+        }
+    }))
+}
+
+// consumes exactly n bytes
+pub fn take_bytes<E: ParserError + 'static>(n: usize) -> Parser<Vec<u8>, E, [u8]> {
+    Parser(Rc::new(move |inp: Input<[u8]>| match inp.rest.get(..n) {
+        Some(taken) => Ok((taken.to_vec(), inp.advance(n))),
+        None => Err(inp.ended(
+            Expected::Label(format!("{} bytes", n)),
+            Needed::Size(n - inp.rest.len()),
+        )),
+    }))
+}
+
+pub fn be_u16<E: ParserError + 'static>() -> Parser<u16, E, [u8]> {
+    map(take_bytes(2), |b| u16::from_be_bytes(b.try_into().unwrap()))
+}
+
+pub fn be_u32<E: ParserError + 'static>() -> Parser<u32, E, [u8]> {
+    map(take_bytes(4), |b| u32::from_be_bytes(b.try_into().unwrap()))
+}
+
+pub fn le_u16<E: ParserError + 'static>() -> Parser<u16, E, [u8]> {
+    map(take_bytes(2), |b| u16::from_le_bytes(b.try_into().unwrap()))
+}
+
+pub fn le_u32<E: ParserError + 'static>() -> Parser<u32, E, [u8]> {
+    map(take_bytes(4), |b| u32::from_le_bytes(b.try_into().unwrap()))
+}
+
+// tokens from a lexer, or anything else in a slice
+
+// the token t
+pub fn token<T: Clone + PartialEq + Debug + 'static, E: ParserError + 'static>(
+    t: T,
+) -> Parser<T, E, [T]> {
+    Parser(Rc::new(move |inp: Input<[T]>| match inp.rest.first() {
+        Some(next) if *next == t => Ok((t.clone(), inp.advance(1))),
+        _ => Err(unexpected(Expected::Literal(format!("{:?}", t)), inp)),
+    }))
+}
+
+// one token satisfying pred
+pub fn token_if<
+    T: Clone + Debug + 'static,
+    F: Fn(&T) -> bool + 'static,
+    E: ParserError + 'static,
+>(
+    pred: F,
+) -> Parser<T, E, [T]> {
+    Parser(Rc::new(move |inp: Input<[T]>| match inp.rest.first() {
+        Some(next) if pred(next) => Ok((next.clone(), inp.advance(1))),
+        _ => Err(unexpected(
+            Expected::Class("matching token".to_string()),
+            inp,
+        )),
+    }))
+}
@@ -0,0 +1,318 @@
+// turning errors into messages for people, compiler style:
+//
+// error[E0001]: expected digit, found "x"
+//  --> 1:5
+//   |
+// 1 | let x
+//   |     ^
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::error::{first_line, Diagnostic, ParseError, Position, Severity};
+
+// renders error with the line of src it happened on and a caret under the spot.
+// src has to be the input that was parsed. every error in a Multiple gets its own snippet
+pub fn render(src: &str, error: &ParseError) -> String {
+    render_as(src, Severity::Error, error)
+}
+
+// like render, but labelled with the diagnostic's severity
+pub fn render_diagnostic(src: &str, diagnostic: &Diagnostic<ParseError>) -> String {
+    render_as(src, diagnostic.severity, &diagnostic.error)
+}
+
+pub fn render_as(src: &str, severity: Severity, error: &ParseError) -> String {
+    match error {
+        ParseError::Multiple(es) => es
+            .iter()
+            .map(|e| render_as(src, severity, e))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ParseError::Repetition(_, e) | ParseError::Cut(e) => render_as(src, severity, e),
+        e => {
+            let label = match e.code() {
+                Some(code) => format!("{}[{}]", severity, code),
+                None => severity.to_string(),
+            };
+            snippet(src, e.position(src), &label, &e.message(), underline_len(e))
+        }
+    }
+}
+
+// a part of the input to point at and what to say about it, as byte offsets.
+// diagnostic crates like ariadne and codespan-reporting are built out of these
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+// labels for error. the first points at what went wrong, followed by one for each thing
+// it happened while parsing, innermost first. an error in a Multiple gets labels of its own
+pub fn labels(error: &ParseError) -> Vec<Label> {
+    marked_labels(error)
+        .into_iter()
+        .map(|(label, _)| label)
+        .collect()
+}
+
+// labels, each with whether it's for what went wrong rather than for the context it was in
+fn marked_labels(error: &ParseError) -> Vec<(Label, bool)> {
+    match error {
+        ParseError::Multiple(es) => es.iter().flat_map(marked_labels).collect(),
+        ParseError::Repetition(_, e) | ParseError::Cut(e) | ParseError::Coded(_, e) => {
+            marked_labels(e)
+        }
+        ParseError::Context(context, e) => {
+            let mut labels = marked_labels(e);
+            let label = Label {
+                span: span(e),
+                message: format!("while parsing {}", context),
+            };
+            labels.push((label, false));
+            labels
+        }
+        e => {
+            let label = Label {
+                span: span(e),
+                message: e.message(),
+            };
+            vec![(label, true)]
+        }
+    }
+}
+
+// error as an ariadne report, pointing at where it happened with a label for each of its
+// labels. the spans are byte offsets, so the report is set up to count bytes rather than chars
+#[cfg(feature = "ariadne")]
+pub fn to_ariadne_report(severity: Severity, error: &ParseError) -> ariadne::Report<'static> {
+    let kind = match severity {
+        Severity::Note => ariadne::ReportKind::Advice,
+        Severity::Warning => ariadne::ReportKind::Warning,
+        Severity::Error => ariadne::ReportKind::Error,
+    };
+    let labels = labels(error);
+    let primary = labels
+        .first()
+        .map_or_else(|| span(error), |l| l.span.clone());
+    let config = ariadne::Config::default().with_index_type(ariadne::IndexType::Byte);
+    let mut report = ariadne::Report::build(kind, primary)
+        .with_config(config)
+        .with_message(error.message())
+        .with_labels(
+            labels
+                .into_iter()
+                .map(|l| ariadne::Label::new(l.span).with_message(l.message)),
+        );
+    if let Some(code) = error.code() {
+        report = report.with_code(code);
+    }
+    report.finish()
+}
+
+// error as a codespan-reporting diagnostic for the file file_id. what went wrong gets a
+// primary label and the context it happened in secondary ones
+#[cfg(feature = "codespan-reporting")]
+pub fn to_codespan_diagnostic<F: Clone>(
+    file_id: F,
+    severity: Severity,
+    error: &ParseError,
+) -> codespan_reporting::diagnostic::Diagnostic<F> {
+    use codespan_reporting::diagnostic;
+
+    let severity = match severity {
+        Severity::Note => diagnostic::Severity::Note,
+        Severity::Warning => diagnostic::Severity::Warning,
+        Severity::Error => diagnostic::Severity::Error,
+    };
+    let labels = marked_labels(error)
+        .into_iter()
+        .map(|(l, primary)| {
+            let label = match primary {
+                true => diagnostic::Label::primary(file_id.clone(), l.span),
+                false => diagnostic::Label::secondary(file_id.clone(), l.span),
+            };
+            label.with_message(l.message)
+        })
+        .collect();
+    let mut diagnostic = diagnostic::Diagnostic::new(severity)
+        .with_message(error.message())
+        .with_labels(labels);
+    if let Some(code) = error.code() {
+        diagnostic = diagnostic.with_code(code);
+    }
+    diagnostic
+}
+
+// what the error found, as far as it's worth pointing at
+fn found(error: &ParseError) -> &str {
+    match error {
+        ParseError::Mismatch(_, _, found)
+        | ParseError::Expected(_, _, Some(found))
+        | ParseError::Unexpected(_, found)
+        | ParseError::Invalid(_, found)
+        | ParseError::Conversion(_, found, _) => first_line(found),
+        ParseError::Context(_, e) | ParseError::Coded(_, e) => found(e),
+        _ => "",
+    }
+}
+
+fn span(error: &ParseError) -> Range<usize> {
+    let at = error.offset();
+    at..at + found(error).len()
+}
+
+// how many chars the caret should cover
+fn underline_len(error: &ParseError) -> usize {
+    found(error).chars().count().max(1)
+}
+
+// label is what's shown before the message, like "error[E0001]"
+fn snippet(src: &str, pos: Position, label: &str, message: &str, underline: usize) -> String {
+    let line_start = src[..pos.offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = src[line_start..].lines().next().unwrap_or("");
+    // tabs are kept so the caret lines up however wide they're shown
+    let pad: String = line
+        .chars()
+        .take(pos.column - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let number = pos.line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!(
+        "{}: {}\n{}--> {}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+        label,
+        message,
+        gutter,
+        pos.line,
+        pos.column,
+        gutter,
+        number,
+        line,
+        gutter,
+        pad,
+        "^".repeat(underline)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combinator::*;
+    use crate::error::*;
+    use crate::primitive::*;
+
+    #[test]
+    fn test_render() {
+        let src = "let a = 1;\nlet\tb = x;";
+        let stmt = || preceded(symbol("let"), terminated(lexeme(identifier()), symbol("=")));
+        let value = || terminated(digit1(), p_char(';'));
+        let program = sep_by1(pair(stmt(), value()), p_char('\n'));
+        let err = all_consuming(program).run(src).unwrap_err();
+        assert_eq!(
+            "error[E0001]: expected end of input, found \"\\n\"\n \
+             --> 1:11\n  |\n1 | let a = 1;\n  |           ^\n",
+            render(src, &err)
+        );
+
+        let err = preceded(take_until("x"), digit()).run(src).unwrap_err();
+        assert_eq!(
+            "error[E0001]: expected digit, found \"x\"\n \
+             --> 2:9\n  |\n2 | let\tb = x;\n  |    \t    ^\n",
+            render(src, &err)
+        );
+    }
+
+    #[test]
+    fn test_render_diagnostic() {
+        let src = "f(1,)";
+        let arg = || emit_warning(terminated(digit1(), p_char(',')), "trailing comma");
+        let call = preceded(p_str("f(".to_string()), terminated(arg(), p_char(')')));
+        let (_, diagnostics) = call.run_collecting(src);
+        assert_eq!(
+            "warning: trailing comma\n --> 1:3\n  |\n1 | f(1,)\n  |   ^\n",
+            render_diagnostic(src, &diagnostics[0])
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let src = "x = y";
+        let value = map_err(digit1(), |e: ParseError| e.with_context("value"));
+        let assignment = map_err(preceded(symbol("x ="), value), |e: ParseError| {
+            e.with_context("assignment")
+        });
+        let err = assignment.run(src).unwrap_err();
+        assert_eq!(
+            vec![
+                Label {
+                    span: 4..5,
+                    message: "expected digit, found \"y\"".to_string()
+                },
+                Label {
+                    span: 4..5,
+                    message: "while parsing value".to_string()
+                },
+                Label {
+                    span: 4..5,
+                    message: "while parsing assignment".to_string()
+                },
+            ],
+            labels(&err)
+        );
+    }
+
+    #[cfg(feature = "ariadne")]
+    #[test]
+    fn test_to_ariadne_report() {
+        let src = "x = y";
+        let value = map_err(digit1(), |e: ParseError| e.with_context("value"));
+        let err = preceded(symbol("x ="), value).run(src).unwrap_err();
+        let mut out = Vec::new();
+        to_ariadne_report(Severity::Error, &err)
+            .write(ariadne::Source::from(src), &mut out)
+            .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("E0001"), "{}", out);
+        assert!(out.contains("expected digit, found \"y\""), "{}", out);
+        assert!(out.contains("while parsing value"), "{}", out);
+    }
+
+    #[cfg(feature = "codespan-reporting")]
+    #[test]
+    fn test_to_codespan_diagnostic() {
+        use codespan_reporting::diagnostic;
+
+        let src = "x = y";
+        let value = map_err(digit1(), |e: ParseError| e.with_context("value"));
+        let err = preceded(symbol("x ="), value).run(src).unwrap_err();
+        let d = to_codespan_diagnostic((), Severity::Warning, &err);
+        assert_eq!(diagnostic::Severity::Warning, d.severity);
+        assert_eq!(Some("E0001".to_string()), d.code);
+        assert_eq!(
+            vec![
+                diagnostic::Label::primary((), 4..5).with_message("expected digit, found \"y\""),
+                diagnostic::Label::secondary((), 4..5).with_message("while parsing value"),
+            ],
+            d.labels
+        );
+    }
+
+    #[test]
+    fn test_message() {
+        let err =
+            (p_str::<ParseError>("https".to_string()) | p_str("http".to_string())).run("ftp://x");
+        assert_eq!(
+            "expected one of https, http, found \"ftp://x\"",
+            err.unwrap_err().message()
+        );
+        let err = repeat(p_char::<ParseError>('a'), 3..=3)
+            .run("aa")
+            .unwrap_err();
+        assert_eq!("expected a, found end of input", err.message());
+    }
+}